@@ -0,0 +1,488 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json, Router};
+use chrono::Utc;
+use entities::{admin_audit, backfill_job, events_tx, webhook_subscription};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, Set,
+};
+use serde_json::json;
+
+use crate::backfill::{
+    backfill_progress, create_backfill_job, recent_backfill_jobs, spawn_backfill_job,
+};
+use crate::cache::fee_grant_key;
+use crate::db_helpers::{set_service_flag, set_tx_executed, FLAG_GRANTING_PAUSED};
+use crate::error::ApiError;
+use crate::fee_grants::{grant, revoke};
+use crate::kado::reconciliation_report;
+use crate::sweeper::sweep;
+use crate::tx_indexer::run_reindexer;
+use crate::types::admin::{AdminIdentity, AdminKey, GrantsQuery, IndexerCursor};
+use crate::types::backfill::{BackfillProgress, BackfillRequest};
+use crate::types::sweeper::SweepReport;
+use crate::types::webhooks::WebhookSubscriptionRequest;
+use crate::webhooks::{create_subscription, deactivate_subscription};
+use crate::AppState;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Parses admin keys formatted as `name:key`, separated by commas
+pub fn parse_admin_keys(keys: &str) -> Result<Vec<AdminKey>, ApiError> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let (name, key) = key.split_once(':').ok_or_else(|| {
+                ApiError::GenericErr("Admin keys should be formatted as name:key".to_string())
+            })?;
+            Ok(AdminKey {
+                name: name.to_string(),
+                key: key.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Compares secrets without leaking where they differ through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Middleware accepting requests with a known key, as a bearer token or in `X-Api-Key`
+pub async fn require_admin_key(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let headers = request.headers();
+    let provided_key = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .ok_or_else(|| ApiError::Unauthorized("missing admin key".to_string()))?;
+
+    let admin_key = state
+        .admin_keys
+        .iter()
+        .find(|admin_key| constant_time_eq(admin_key.key.as_bytes(), provided_key.as_bytes()))
+        .ok_or_else(|| ApiError::Unauthorized("invalid admin key".to_string()))?;
+
+    let identity = AdminIdentity {
+        key_name: admin_key.name.clone(),
+    };
+    request.extensions_mut().insert(identity);
+
+    Ok(next.run(request).await)
+}
+
+pub fn admin_router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/grants", get(list_grants))
+        .route("/grants/:address", post(admin_grant).delete(admin_revoke))
+        .route("/index/:address", post(admin_reindex))
+        .route(
+            "/executed/:address/:txhash",
+            post(mark_executed).delete(unmark_executed),
+        )
+        .route("/granting/pause", post(pause_granting))
+        .route("/granting/resume", post(resume_granting))
+        .route("/indexer", get(indexer_cursors))
+        .route("/kado/reconciliation", get(reconciliation_report))
+        .route("/sweeper/report", get(sweeper_report))
+        .route("/sweeper/run", post(run_sweeper))
+        .route("/backfills", get(list_backfills).post(start_backfill))
+        .route("/backfills/:id", get(get_backfill))
+        .route("/backfills/:id/resume", post(resume_backfill))
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/webhooks/:id", delete(delete_webhook))
+        .route_layer(middleware::from_fn_with_state(state, require_admin_key))
+}
+
+/// Records an admin action with the key that made it
+pub async fn audit(
+    db: &DatabaseConnection,
+    identity: &AdminIdentity,
+    action: &str,
+    target: Option<String>,
+    details: Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    tracing::info!("Admin {} did {} on {:?}", identity.key_name, action, target);
+    admin_audit::ActiveModel {
+        key_name: Set(identity.key_name.clone()),
+        action: Set(action.to_string()),
+        target: Set(target),
+        details: Set(details),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+/// Records an admin action whether it succeeded or not, failures are saved with their error
+pub async fn audit_outcome<T>(
+    db: &DatabaseConnection,
+    identity: &AdminIdentity,
+    action: &str,
+    target: Option<String>,
+    outcome: &Result<T, ApiError>,
+    details: impl FnOnce(&T) -> Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    let details = match outcome {
+        Ok(value) => details(value),
+        Err(e) => Some(json!({ "error": e.to_string() })),
+    };
+    let action = match outcome {
+        Ok(_) => action.to_string(),
+        Err(_) => format!("{}_failed", action),
+    };
+    audit(db, identity, &action, target, details).await
+}
+
+async fn list_grants(
+    Query(query): Query<GrantsQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<events_tx::Model>>, ApiError> {
+    let grants = events_tx::Entity::find()
+        .filter(events_tx::Column::HasFeeGrant.eq(true))
+        .apply_if(query.address, |q, address| {
+            q.filter(events_tx::Column::Address.eq(address))
+        })
+        .order_by_desc(events_tx::Column::Id)
+        .limit(
+            query
+                .limit
+                .unwrap_or(state.config.pagination.admin_grants_limit),
+        )
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(grants))
+}
+
+/// Grants an allowance, without the risk checks
+async fn admin_grant(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<String, ApiError> {
+    let outcome = grant(&state, address.clone()).await;
+    state
+        .cache
+        .invalidate(&fee_grant_key(&state.sender, &address))
+        .await;
+
+    audit_outcome(
+        &state.db,
+        &identity,
+        "grant",
+        Some(address),
+        &outcome,
+        |response| Some(json!({ "response": response })),
+    )
+    .await?;
+    outcome
+}
+
+async fn admin_revoke(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<String, ApiError> {
+    let outcome = revoke(&state, address.clone()).await;
+    state
+        .cache
+        .invalidate(&fee_grant_key(&state.sender, &address))
+        .await;
+
+    audit_outcome(
+        &state.db,
+        &identity,
+        "revoke",
+        Some(address),
+        &outcome,
+        |response| Some(json!({ "response": response })),
+    )
+    .await?;
+    outcome
+}
+
+/// Walks all the tx pages of the address again
+async fn admin_reindex(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<(), ApiError> {
+    let outcome = run_reindexer(state.clone(), address.clone()).await;
+    audit_outcome(
+        &state.db,
+        &identity,
+        "reindex",
+        Some(address),
+        &outcome,
+        |_| None,
+    )
+    .await?;
+    outcome
+}
+
+async fn mark_executed(
+    Path((address, txhash)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<bool>, ApiError> {
+    set_executed(&state, &identity, address, txhash, true).await
+}
+
+async fn unmark_executed(
+    Path((address, txhash)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<bool>, ApiError> {
+    set_executed(&state, &identity, address, txhash, false).await
+}
+
+async fn set_executed(
+    state: &AppState,
+    identity: &AdminIdentity,
+    address: String,
+    txhash: String,
+    executed: bool,
+) -> Result<Json<bool>, ApiError> {
+    let outcome = set_tx_executed(address.clone(), txhash.clone(), executed, &state.db).await;
+    let action = if executed {
+        "mark_executed"
+    } else {
+        "unmark_executed"
+    };
+    audit_outcome(
+        &state.db,
+        identity,
+        action,
+        Some(address),
+        &outcome,
+        |found| Some(json!({ "tx_hash": txhash, "found": found })),
+    )
+    .await?;
+    outcome.map(Json)
+}
+
+async fn pause_granting(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<(), ApiError> {
+    set_granting_paused(&state, &identity, true).await
+}
+
+async fn resume_granting(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<(), ApiError> {
+    set_granting_paused(&state, &identity, false).await
+}
+
+/// The flag is saved in the database, so that it applies to every replica and survives restarts
+async fn set_granting_paused(
+    state: &AppState,
+    identity: &AdminIdentity,
+    paused: bool,
+) -> Result<(), ApiError> {
+    let outcome =
+        set_service_flag(&state.db, FLAG_GRANTING_PAUSED, paused, &identity.key_name).await;
+    let action = if paused {
+        "pause_granting"
+    } else {
+        "resume_granting"
+    };
+    audit_outcome(&state.db, identity, action, None, &outcome, |_| None).await?;
+    outcome
+}
+
+async fn indexer_cursors(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<IndexerCursor>>, ApiError> {
+    Ok(Json(query_indexer_cursors(&state.db).await?))
+}
+
+/// Number of txs indexed and the latest tx, by address
+pub async fn query_indexer_cursors(
+    db: &DatabaseConnection,
+) -> Result<Vec<IndexerCursor>, ApiError> {
+    Ok(events_tx::Entity::find()
+        .select_only()
+        .column(events_tx::Column::Address)
+        .column_as(Expr::col(events_tx::Column::Id).count(), "indexed")
+        .column_as(
+            Expr::col(events_tx::Column::Timestamp).max(),
+            "last_timestamp",
+        )
+        .group_by(events_tx::Column::Address)
+        .into_model::<IndexerCursor>()
+        .all(db)
+        .await?)
+}
+
+/// Lists the grants the sweeper would revoke, without revoking them
+async fn sweeper_report(State(state): State<Arc<AppState>>) -> Result<Json<SweepReport>, ApiError> {
+    Ok(Json(sweep(&state, true).await?))
+}
+
+async fn run_sweeper(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<SweepReport>, ApiError> {
+    let outcome = sweep(&state, false).await;
+    audit_outcome(&state.db, &identity, "sweep", None, &outcome, |report| {
+        Some(json!({
+            "revoked": report.candidates.len(),
+            "txs": report.revoke_txs,
+            "errors": report.errors,
+        }))
+    })
+    .await?;
+    outcome.map(Json)
+}
+
+/// Creates a backfill job and runs it in the background
+async fn start_backfill(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(request): Json<BackfillRequest>,
+) -> Result<Json<backfill_job::Model>, ApiError> {
+    let outcome = create_backfill_job(&state, request, &identity.key_name).await;
+    audit_outcome(&state.db, &identity, "backfill", None, &outcome, |job| {
+        Some(json!({ "job_id": job.id }))
+    })
+    .await?;
+    let job = outcome?;
+    spawn_backfill_job(state, job.id);
+    Ok(Json(job))
+}
+
+async fn list_backfills(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<backfill_job::Model>>, ApiError> {
+    Ok(Json(recent_backfill_jobs(&state.db).await?))
+}
+
+async fn get_backfill(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<BackfillProgress>, ApiError> {
+    Ok(Json(backfill_progress(&state.db, id).await?))
+}
+
+/// Runs the segments left, including the failed ones
+async fn resume_backfill(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<BackfillProgress>, ApiError> {
+    let outcome = backfill_progress(&state.db, id).await;
+    audit_outcome(
+        &state.db,
+        &identity,
+        "resume_backfill",
+        None,
+        &outcome,
+        |_| Some(json!({ "job_id": id })),
+    )
+    .await?;
+    let progress = outcome?;
+    spawn_backfill_job(state, id);
+    Ok(Json(progress))
+}
+
+async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<webhook_subscription::Model>>, ApiError> {
+    Ok(Json(
+        webhook_subscription::Entity::find()
+            .order_by_asc(webhook_subscription::Column::Id)
+            .all(&state.db)
+            .await?,
+    ))
+}
+
+async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(request): Json<WebhookSubscriptionRequest>,
+) -> Result<Json<webhook_subscription::Model>, ApiError> {
+    let url = request.url.clone();
+    let outcome = create_subscription(&state.db, request).await;
+    audit_outcome(
+        &state.db,
+        &identity,
+        "create_webhook",
+        Some(url),
+        &outcome,
+        |subscription| Some(json!({ "id": subscription.id, "events": subscription.events })),
+    )
+    .await?;
+    outcome.map(Json)
+}
+
+async fn delete_webhook(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<webhook_subscription::Model>, ApiError> {
+    let outcome = deactivate_subscription(&state.db, id).await;
+    audit_outcome(
+        &state.db,
+        &identity,
+        "delete_webhook",
+        None,
+        &outcome,
+        |subscription| Some(json!({ "id": id, "url": subscription.url })),
+    )
+    .await?;
+    outcome.map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_keys_are_parsed() {
+        let keys = parse_admin_keys(" alice:key-1 ,bob:key:2,, ").unwrap();
+        let keys = keys
+            .iter()
+            .map(|key| (key.name.as_str(), key.key.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("alice", "key-1"), ("bob", "key:2")]);
+    }
+
+    #[test]
+    fn no_admin_keys_is_valid() {
+        assert!(parse_admin_keys("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn admin_keys_need_a_name() {
+        assert!(parse_admin_keys("alice:key-1,key-2").is_err());
+    }
+
+    #[test]
+    fn secrets_are_compared_entirely() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
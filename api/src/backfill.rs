@@ -0,0 +1,654 @@
+use std::collections::{HashMap, HashSet};
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone, Utc};
+use cosmos_sdk_proto::cosmos::base::abci::v1beta1::TxResponse;
+use cosmos_sdk_proto::cosmos::base::tendermint::v1beta1::service_client::ServiceClient as TendermintClient;
+use cosmos_sdk_proto::cosmos::base::tendermint::v1beta1::{
+    GetBlockByHeightRequest, GetLatestBlockRequest,
+};
+use cosmos_sdk_proto::cosmos::tx::v1beta1::OrderBy;
+use entities::{backfill_job, backfill_segment, events_tx};
+use futures::{stream, StreamExt};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use serde_json::json;
+
+use crate::cache::tx_total_key;
+use crate::db_helpers::add_txs_to_db;
+use crate::error::ApiError;
+use crate::grpc::GrpcChannel;
+use crate::tx_indexer::{events_from_address, get_txs_event, publish_deposits};
+use crate::types::backfill::{
+    BackfillProgress, BackfillRequest, BackfillStatus, BackfillTargetKind,
+};
+use crate::AppState;
+
+/// Jobs listed by the admin API and the CLI
+const RECENT_JOBS_LIMIT: u64 = 50;
+
+pub(crate) async fn latest_height(channel: GrpcChannel) -> Result<i64, ApiError> {
+    TendermintClient::new(channel)
+        .get_latest_block(GetLatestBlockRequest {})
+        .await?
+        .into_inner()
+        .block
+        .and_then(|block| block.header)
+        .map(|header| header.height)
+        .ok_or_else(|| ApiError::GenericErr("the latest block has no header".to_string()))
+}
+
+async fn block_time(channel: GrpcChannel, height: i64) -> Result<DateTime<Utc>, ApiError> {
+    TendermintClient::new(channel)
+        .get_block_by_height(GetBlockByHeightRequest { height })
+        .await?
+        .into_inner()
+        .block
+        .and_then(|block| block.header)
+        .and_then(|header| header.time)
+        .and_then(|time| Utc.timestamp_opt(time.seconds, time.nanos as u32).single())
+        .ok_or_else(|| ApiError::GenericErr(format!("block {} has no time", height)))
+}
+
+/// First block produced at or after the date, `latest + 1` if there is none yet
+async fn first_height_after(
+    channel: GrpcChannel,
+    date: DateTime<Utc>,
+    latest: i64,
+) -> Result<i64, ApiError> {
+    let (mut low, mut high) = (1, latest + 1);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if block_time(channel.clone(), middle).await? >= date {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Resolves the range to heights and splits the job in segments, which are scanned later
+pub async fn create_backfill_job(
+    state: &AppState,
+    request: BackfillRequest,
+    created_by: &str,
+) -> Result<backfill_job::Model, ApiError> {
+    let channel = state.channel.clone();
+    let latest = latest_height(channel.clone()).await?;
+
+    let from_height = match (request.from_height, request.from_date) {
+        (Some(height), _) => height,
+        (None, Some(date)) => first_height_after(channel.clone(), date, latest).await?,
+        (None, None) => 1,
+    };
+    let to_height = match (request.to_height, request.to_date) {
+        (Some(height), _) => height.min(latest),
+        (None, Some(date)) => first_height_after(channel, date, latest).await? - 1,
+        (None, None) => latest,
+    };
+    if from_height < 1 || from_height > to_height {
+        return Err(ApiError::InvalidQuery(format!(
+            "empty backfill range, from height {} to {}",
+            from_height, to_height
+        )));
+    }
+
+    let (kind, targets) = if request.addresses.is_empty() {
+        (
+            BackfillTargetKind::Denom,
+            state.config.indexer.deposit_denoms.clone(),
+        )
+    } else {
+        (BackfillTargetKind::Address, request.addresses)
+    };
+
+    // Addresses have few txs, only the denom scans are worth splitting
+    let segment_blocks = match kind {
+        BackfillTargetKind::Address => to_height - from_height + 1,
+        BackfillTargetKind::Denom => state.config.backfill.segment_blocks as i64,
+    };
+    let segments = targets
+        .iter()
+        .flat_map(|target| {
+            (from_height..=to_height)
+                .step_by(segment_blocks as usize)
+                .map(move |start| {
+                    (
+                        target.clone(),
+                        start,
+                        (start + segment_blocks - 1).min(to_height),
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let now = Utc::now();
+    let txn = state.db.begin().await?;
+    let job = backfill_job::ActiveModel {
+        target_kind: Set(kind.as_str().to_string()),
+        targets: Set(json!(targets)),
+        from_height: Set(from_height),
+        to_height: Set(to_height),
+        status: Set(BackfillStatus::Pending.as_str().to_string()),
+        segments_total: Set(segments.len() as i32),
+        segments_done: Set(0),
+        txs_saved: Set(0),
+        deposits_found: Set(0),
+        created_by: Set(created_by.to_string()),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    backfill_segment::Entity::insert_many(segments.into_iter().map(|(target, from, to)| {
+        backfill_segment::ActiveModel {
+            job_id: Set(job.id),
+            target: Set(target),
+            from_height: Set(from),
+            to_height: Set(to),
+            status: Set(BackfillStatus::Pending.as_str().to_string()),
+            txs_saved: Set(0),
+            deposits_found: Set(0),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+    }))
+    .exec_without_returning(&txn)
+    .await?;
+    txn.commit().await?;
+
+    tracing::info!(
+        "Created backfill {} of {} {:?} from height {} to {}",
+        job.id,
+        kind.as_str(),
+        job.targets,
+        from_height,
+        to_height
+    );
+    Ok(job)
+}
+
+/// Runs the job in the background, it's resumed on the next start if interrupted
+pub fn spawn_backfill_job(state: Arc<AppState>, job_id: i32) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        if let Err(e) = run_backfill_job(state, job_id).await {
+            tracing::error!("Backfill {} failed: {}", job_id, e);
+        }
+    });
+}
+
+/// Scans the segments that are not completed yet, a few at a time.
+/// Failed segments are retried, segments held by another replica are skipped
+pub async fn run_backfill_job(
+    state: Arc<AppState>,
+    job_id: i32,
+) -> Result<BackfillProgress, ApiError> {
+    let job = find_job(&state.db, job_id).await?;
+    let kind = BackfillTargetKind::parse(&job.target_kind).ok_or_else(|| {
+        ApiError::GenericErr(format!("unknown backfill target {}", job.target_kind))
+    })?;
+
+    update_job_status(&state.db, job_id, BackfillStatus::Running, false).await?;
+    let segments = backfill_segment::Entity::find()
+        .filter(backfill_segment::Column::JobId.eq(job_id))
+        .filter(backfill_segment::Column::Status.ne(BackfillStatus::Completed.as_str()))
+        .order_by_asc(backfill_segment::Column::Id)
+        .all(&state.db)
+        .await?;
+
+    stream::iter(segments)
+        .for_each_concurrent(state.config.backfill.concurrency, |segment| {
+            run_segment(&state, kind, segment)
+        })
+        .await;
+
+    finish_job(&state.db, job_id).await
+}
+
+/// Resumes the unfinished jobs at start, then every segment lease, so that the segments
+/// left running by a crashed runner are taken over once their lease expires
+pub fn spawn_backfill_resumer(state: Arc<AppState>) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        let lease = state
+            .config
+            .backfill
+            .segment_lease()
+            .to_std()
+            .unwrap_or_default();
+        let mut interval = tokio::time::interval(lease);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => return,
+            }
+            if let Err(e) = resume_backfill_jobs(state.clone()).await {
+                tracing::error!("Could not resume the backfills: {}", e);
+            }
+        }
+    });
+}
+
+/// Runs the pending and running jobs again, their completed segments are skipped
+pub async fn resume_backfill_jobs(state: Arc<AppState>) -> Result<(), ApiError> {
+    let jobs = backfill_job::Entity::find()
+        .filter(backfill_job::Column::Status.is_in([
+            BackfillStatus::Pending.as_str(),
+            BackfillStatus::Running.as_str(),
+        ]))
+        .order_by_asc(backfill_job::Column::Id)
+        .all(&state.db)
+        .await?;
+
+    for job in jobs {
+        if state.shutdown.is_cancelled() {
+            break;
+        }
+        tracing::info!("Resuming backfill {}", job.id);
+        if let Err(e) = run_backfill_job(state.clone(), job.id).await {
+            tracing::error!("Could not resume backfill {}: {}", job.id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn run_segment(state: &AppState, kind: BackfillTargetKind, segment: backfill_segment::Model) {
+    // The concurrency is shared by all the jobs
+    let Ok(_slot) = state.backfill_slots.acquire().await else {
+        return;
+    };
+    if state.shutdown.is_cancelled() {
+        return;
+    }
+    let segment_id = segment.id;
+    let result = async {
+        if !claim_segment(state, segment_id).await? {
+            tracing::info!("Backfill segment {} is scanned elsewhere", segment_id);
+            return Ok(());
+        }
+        let status = match scan_segment(state, kind, &segment).await {
+            Ok(true) => {
+                tracing::info!(
+                    "Backfill segment {} of {} ({} to {}) completed",
+                    segment_id,
+                    segment.target,
+                    segment.from_height,
+                    segment.to_height
+                );
+                (BackfillStatus::Completed, None)
+            }
+            // Interrupted by a stop, the saved page is the next start
+            Ok(false) => (BackfillStatus::Pending, None),
+            Err(e) => {
+                tracing::error!("Backfill segment {} failed: {}", segment_id, e);
+                (BackfillStatus::Failed, Some(e.to_string()))
+            }
+        };
+        release_segment(&state.db, &segment, status).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Could not update backfill segment {}: {}", segment_id, e);
+    }
+}
+
+/// Takes the segment, unless another runner holds it and updated it recently
+async fn claim_segment(state: &AppState, segment_id: i32) -> Result<bool, ApiError> {
+    let now = Utc::now();
+    let stale = now - state.config.backfill.segment_lease();
+    let claimed = backfill_segment::Entity::update_many()
+        .col_expr(
+            backfill_segment::Column::Status,
+            Expr::value(BackfillStatus::Running.as_str()),
+        )
+        .col_expr(backfill_segment::Column::UpdatedAt, Expr::value(now))
+        .filter(backfill_segment::Column::Id.eq(segment_id))
+        .filter(
+            Condition::any()
+                .add(backfill_segment::Column::Status.is_in([
+                    BackfillStatus::Pending.as_str(),
+                    BackfillStatus::Failed.as_str(),
+                ]))
+                .add(
+                    Condition::all()
+                        .add(backfill_segment::Column::Status.eq(BackfillStatus::Running.as_str()))
+                        .add(backfill_segment::Column::UpdatedAt.lt(stale)),
+                ),
+        )
+        .exec(&state.db)
+        .await?;
+    Ok(claimed.rows_affected == 1)
+}
+
+async fn release_segment(
+    db: &DatabaseConnection,
+    segment: &backfill_segment::Model,
+    (status, error): (BackfillStatus, Option<String>),
+) -> Result<(), ApiError> {
+    backfill_segment::Entity::update_many()
+        .col_expr(
+            backfill_segment::Column::Status,
+            Expr::value(status.as_str()),
+        )
+        .col_expr(backfill_segment::Column::Error, Expr::value(error))
+        .col_expr(backfill_segment::Column::UpdatedAt, Expr::value(Utc::now()))
+        .filter(backfill_segment::Column::Id.eq(segment.id))
+        .exec(db)
+        .await?;
+
+    if status == BackfillStatus::Completed {
+        backfill_job::Entity::update_many()
+            .col_expr(
+                backfill_job::Column::SegmentsDone,
+                Expr::col(backfill_job::Column::SegmentsDone).add(1),
+            )
+            .col_expr(backfill_job::Column::UpdatedAt, Expr::value(Utc::now()))
+            .filter(backfill_job::Column::Id.eq(segment.job_id))
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+fn target_events(kind: BackfillTargetKind, target: &String) -> Vec<String> {
+    match kind {
+        BackfillTargetKind::Address => events_from_address(target),
+        BackfillTargetKind::Denom => vec![format!("denomination_trace.denom='{}'", target)],
+    }
+}
+
+/// Saves the txs of the segment range, page by page, oldest first.
+/// Returns false if it was interrupted by a stop before the end of the range
+async fn scan_segment(
+    state: &AppState,
+    kind: BackfillTargetKind,
+    segment: &backfill_segment::Model,
+) -> Result<bool, ApiError> {
+    let events = target_events(kind, &segment.target);
+    let limit = state.config.indexer.page_size;
+    let heights = segment.from_height..=segment.to_height;
+
+    let first_page = match segment.next_page {
+        Some(page) => Some(page as u64),
+        None => first_page_at(state.channel.clone(), &events, segment.from_height, limit).await?,
+    };
+    let Some(mut page) = first_page else {
+        return Ok(true);
+    };
+
+    loop {
+        if state.shutdown.is_cancelled() {
+            return Ok(false);
+        }
+        let response = get_txs_event(
+            state.channel.clone(),
+            events.clone(),
+            page,
+            limit,
+            OrderBy::Asc,
+        )
+        .await?;
+        let txs = response.tx_responses;
+        // Other txs of the last block can be on the next page
+        let past_range = txs.last().map_or(true, |tx| tx.height > segment.to_height);
+        let txs = txs
+            .into_iter()
+            .filter(|tx| heights.contains(&tx.height))
+            .collect::<Vec<_>>();
+
+        let (txs_saved, deposits_found) = save_txs(state, kind, &segment.target, txs).await?;
+        record_page(&state.db, segment, page + 1, txs_saved, deposits_found).await?;
+
+        if past_range || page * limit >= response.total {
+            return Ok(true);
+        }
+        page += 1;
+    }
+}
+
+/// First page, oldest first, with txs at or after the height. Pages are searched by bisection
+async fn first_page_at(
+    channel: GrpcChannel,
+    events: &[String],
+    height: i64,
+    limit: u64,
+) -> Result<Option<u64>, ApiError> {
+    let total = get_txs_event(channel.clone(), events.to_vec(), 1, 1, OrderBy::Asc)
+        .await?
+        .total;
+    if total == 0 {
+        return Ok(None);
+    }
+
+    let (mut low, mut high) = (1, total.div_ceil(limit));
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let last_height = get_txs_event(
+            channel.clone(),
+            events.to_vec(),
+            middle,
+            limit,
+            OrderBy::Asc,
+        )
+        .await?
+        .tx_responses
+        .last()
+        .map_or(i64::MAX, |tx| tx.height);
+        if last_height >= height {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(Some(low))
+}
+
+/// Receiver of the IBC transfer, the address the deposit is indexed for
+fn transfer_receiver(tx: &TxResponse) -> Option<String> {
+    tx.events
+        .iter()
+        .find(|e| e.r#type == "fungible_token_packet")
+        .and_then(|e| e.attributes.iter().find(|a| a.key == "receiver"))
+        .and_then(|a| from_utf8(a.value.as_ref()).ok())
+        .map(|receiver| receiver.to_string())
+}
+
+/// Upserts the txs by receiver and publishes the deposits that were missing.
+/// Returns the number of txs and deposits that were missing
+async fn save_txs(
+    state: &AppState,
+    kind: BackfillTargetKind,
+    target: &String,
+    txs: Vec<TxResponse>,
+) -> Result<(u64, u64), ApiError> {
+    let mut txs_by_address: HashMap<String, Vec<TxResponse>> = HashMap::new();
+    for tx in txs {
+        let address = match kind {
+            BackfillTargetKind::Address => Some(target.clone()),
+            BackfillTargetKind::Denom => transfer_receiver(&tx),
+        };
+        if let Some(address) = address {
+            txs_by_address.entry(address).or_default().push(tx);
+        }
+    }
+
+    let (mut txs_saved, mut deposits_found) = (0, 0);
+    for (address, txs) in txs_by_address {
+        let hashes = txs.iter().map(|tx| tx.txhash.clone()).collect::<Vec<_>>();
+        let known_hashes = events_tx::Entity::find()
+            .select_only()
+            .column(events_tx::Column::TxHash)
+            .filter(events_tx::Column::Address.eq(address.clone()))
+            .filter(events_tx::Column::TxHash.is_in(hashes.clone()))
+            .into_tuple::<String>()
+            .all(&state.db)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let deposits = add_txs_to_db(
+            address.clone(),
+            txs,
+            &state.config.indexer.deposit_denoms,
+            &state.db,
+        )
+        .await?
+        .into_iter()
+        .filter(|deposit| !known_hashes.contains(&deposit.tx_hash))
+        .collect::<Vec<_>>();
+
+        let missing_txs = (hashes.len() - known_hashes.len()) as u64;
+        if missing_txs > 0 {
+            state.cache.invalidate(&tx_total_key(&address)).await;
+        }
+        txs_saved += missing_txs;
+        deposits_found += deposits.len() as u64;
+        publish_deposits(state, address, deposits).await?;
+    }
+    Ok((txs_saved, deposits_found))
+}
+
+/// Saves the cursor of the segment, which also renews its lease, and the job counters
+async fn record_page(
+    db: &DatabaseConnection,
+    segment: &backfill_segment::Model,
+    next_page: u64,
+    txs_saved: u64,
+    deposits_found: u64,
+) -> Result<(), ApiError> {
+    let now = Utc::now();
+    backfill_segment::Entity::update_many()
+        .col_expr(
+            backfill_segment::Column::NextPage,
+            Expr::value(next_page as i64),
+        )
+        .col_expr(
+            backfill_segment::Column::TxsSaved,
+            Expr::col(backfill_segment::Column::TxsSaved).add(txs_saved),
+        )
+        .col_expr(
+            backfill_segment::Column::DepositsFound,
+            Expr::col(backfill_segment::Column::DepositsFound).add(deposits_found),
+        )
+        .col_expr(backfill_segment::Column::UpdatedAt, Expr::value(now))
+        .filter(backfill_segment::Column::Id.eq(segment.id))
+        .exec(db)
+        .await?;
+
+    if txs_saved > 0 {
+        backfill_job::Entity::update_many()
+            .col_expr(
+                backfill_job::Column::TxsSaved,
+                Expr::col(backfill_job::Column::TxsSaved).add(txs_saved),
+            )
+            .col_expr(
+                backfill_job::Column::DepositsFound,
+                Expr::col(backfill_job::Column::DepositsFound).add(deposits_found),
+            )
+            .col_expr(backfill_job::Column::UpdatedAt, Expr::value(now))
+            .filter(backfill_job::Column::Id.eq(segment.job_id))
+            .exec(db)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn update_job_status(
+    db: &DatabaseConnection,
+    job_id: i32,
+    status: BackfillStatus,
+    finished: bool,
+) -> Result<(), ApiError> {
+    let now = Utc::now();
+    backfill_job::Entity::update_many()
+        .col_expr(backfill_job::Column::Status, Expr::value(status.as_str()))
+        .col_expr(backfill_job::Column::UpdatedAt, Expr::value(now))
+        .col_expr(
+            backfill_job::Column::FinishedAt,
+            Expr::value(finished.then_some(now)),
+        )
+        .filter(backfill_job::Column::Id.eq(job_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Completes the job once all the segments are, it stays running while some are left
+async fn finish_job(db: &DatabaseConnection, job_id: i32) -> Result<BackfillProgress, ApiError> {
+    let segments_with_status = |status: BackfillStatus| {
+        backfill_segment::Entity::find()
+            .filter(backfill_segment::Column::JobId.eq(job_id))
+            .filter(backfill_segment::Column::Status.eq(status.as_str()))
+            .count(db)
+    };
+    let left = segments_with_status(BackfillStatus::Pending).await?
+        + segments_with_status(BackfillStatus::Running).await?;
+    let failed = segments_with_status(BackfillStatus::Failed).await?;
+
+    match (left, failed) {
+        (0, 0) => update_job_status(db, job_id, BackfillStatus::Completed, true).await?,
+        (0, _) => update_job_status(db, job_id, BackfillStatus::Failed, true).await?,
+        _ => {}
+    }
+    backfill_progress(db, job_id).await
+}
+
+async fn find_job(db: &DatabaseConnection, job_id: i32) -> Result<backfill_job::Model, ApiError> {
+    backfill_job::Entity::find_by_id(job_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::InvalidQuery(format!("unknown backfill job {}", job_id)))
+}
+
+pub async fn backfill_progress(
+    db: &DatabaseConnection,
+    job_id: i32,
+) -> Result<BackfillProgress, ApiError> {
+    let job = find_job(db, job_id).await?;
+    let errors = backfill_segment::Entity::find()
+        .filter(backfill_segment::Column::JobId.eq(job_id))
+        .filter(backfill_segment::Column::Status.eq(BackfillStatus::Failed.as_str()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|segment| {
+            format!(
+                "{} from {} to {}: {}",
+                segment.target,
+                segment.from_height,
+                segment.to_height,
+                segment.error.unwrap_or_default()
+            )
+        })
+        .collect();
+    let percent = if job.segments_total == 0 {
+        100.0
+    } else {
+        f64::from(job.segments_done) * 100.0 / f64::from(job.segments_total)
+    };
+
+    Ok(BackfillProgress {
+        job,
+        percent,
+        errors,
+    })
+}
+
+/// Latest jobs first
+pub async fn recent_backfill_jobs(
+    db: &DatabaseConnection,
+) -> Result<Vec<backfill_job::Model>, ApiError> {
+    Ok(backfill_job::Entity::find()
+        .order_by_desc(backfill_job::Column::Id)
+        .limit(RECENT_JOBS_LIMIT)
+        .all(db)
+        .await?)
+}
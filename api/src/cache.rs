@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::ApiError;
+
+/// Above this size, expired entries are purged from the in-process cache on insertion
+const MEMORY_CACHE_PURGE_SIZE: usize = 10_000;
+
+pub fn tx_total_key(address: &str) -> String {
+    format!("tx-total:{}", address)
+}
+
+pub fn fee_grant_key(granter: &str, grantee: &str) -> String {
+    format!("fee-grant:{}:{}", granter, grantee)
+}
+
+/// How long the chain query results stay in cache
+pub struct CacheTtls {
+    pub tx_total: Duration,
+    pub fee_grant: Duration,
+}
+
+enum CacheBackend {
+    Redis(ConnectionManager),
+    Memory(Mutex<HashMap<String, (Instant, String)>>),
+}
+
+/// JSON cache stored in Redis when available, in process otherwise.
+/// Cache errors are logged and handled as cache misses, they never fail a request
+pub struct Cache {
+    backend: CacheBackend,
+    pub ttls: CacheTtls,
+}
+
+impl Cache {
+    pub async fn new(redis_url: Option<String>, ttls: CacheTtls) -> Self {
+        let backend = match redis_url {
+            Some(redis_url) => match connect(&redis_url).await {
+                Ok(connection) => CacheBackend::Redis(connection),
+                Err(e) => {
+                    tracing::error!(
+                        "Could not connect to Redis, using the in-process cache: {}",
+                        e
+                    );
+                    CacheBackend::Memory(Default::default())
+                }
+            },
+            None => CacheBackend::Memory(Default::default()),
+        };
+        Self { backend, ttls }
+    }
+
+    /// Redis connection shared with the other features, if Redis is used
+    pub fn redis(&self) -> Option<ConnectionManager> {
+        match &self.backend {
+            CacheBackend::Redis(connection) => Some(connection.clone()),
+            CacheBackend::Memory(_) => None,
+        }
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = match &self.backend {
+            CacheBackend::Redis(connection) => connection
+                .clone()
+                .get::<_, Option<String>>(key)
+                .await
+                .map_err(|e| tracing::warn!("Redis get {} failed: {}", key, e))
+                .ok()
+                .flatten(),
+            CacheBackend::Memory(entries) => {
+                let entries = entries.lock().unwrap();
+                entries
+                    .get(key)
+                    .filter(|(expires_at, _)| *expires_at > Instant::now())
+                    .map(|(_, value)| value.clone())
+            }
+        }?;
+
+        serde_json::from_str(&value)
+            .map_err(|e| tracing::warn!("Invalid cached value for {}: {}", key, e))
+            .ok()
+    }
+
+    pub async fn set<T: Serialize>(&self, key: &str, value: &T, ttl: Duration) {
+        let value = match serde_json::to_string(value) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Could not serialize the cached value for {}: {}", key, e);
+                return;
+            }
+        };
+
+        match &self.backend {
+            CacheBackend::Redis(connection) => {
+                if let Err(e) = connection
+                    .clone()
+                    .set_ex::<_, _, ()>(key, value, ttl.as_secs().max(1))
+                    .await
+                {
+                    tracing::warn!("Redis set {} failed: {}", key, e);
+                }
+            }
+            CacheBackend::Memory(entries) => {
+                let mut entries = entries.lock().unwrap();
+                let now = Instant::now();
+                if entries.len() >= MEMORY_CACHE_PURGE_SIZE {
+                    entries.retain(|_, (expires_at, _)| *expires_at > now);
+                }
+                entries.insert(key.to_string(), (now + ttl, value));
+            }
+        }
+    }
+
+    pub async fn invalidate(&self, key: &str) {
+        match &self.backend {
+            CacheBackend::Redis(connection) => {
+                if let Err(e) = connection.clone().del::<_, ()>(key).await {
+                    tracing::warn!("Redis del {} failed: {}", key, e);
+                }
+            }
+            CacheBackend::Memory(entries) => {
+                entries.lock().unwrap().remove(key);
+            }
+        }
+    }
+
+    /// Returns the cached value or computes and caches it
+    pub async fn get_or_insert<T, F>(&self, key: &str, ttl: Duration, f: F) -> Result<T, ApiError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: std::future::Future<Output = Result<T, ApiError>>,
+    {
+        if let Some(value) = self.get(key).await {
+            return Ok(value);
+        }
+        let value = f.await?;
+        self.set(key, &value, ttl).await;
+        Ok(value)
+    }
+}
+
+async fn connect(redis_url: &str) -> Result<ConnectionManager, ApiError> {
+    let client = redis::Client::open(redis_url)?;
+    Ok(client.get_connection_manager().await?)
+}
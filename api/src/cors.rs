@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::{header::ORIGIN, HeaderName, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+use crate::error::ApiError;
+use crate::telemetry::REQUEST_ID_HEADER;
+use crate::versioning::DEPRECATION_HEADER;
+use crate::AppState;
+
+/// Response headers the frontends can read
+const EXPOSED_HEADERS: [&str; 3] = ["retry-after", "idempotent-replayed", "link"];
+
+/// CORS of the read only routes, open to the allowed and the mutating origins
+pub fn public_cors(config: &CorsConfig) -> CorsLayer {
+    let origins = config
+        .allowed_origins
+        .iter()
+        .chain(&config.mutating_origins);
+    cors_layer(config, origins)
+}
+
+/// CORS of the routes that spend LUNA or gRPC queries, open to the official frontends only
+pub fn mutating_cors(config: &CorsConfig) -> CorsLayer {
+    cors_layer(config, config.mutating_origins.iter())
+}
+
+/// CORS only hides the responses from the browsers, the requests of other websites still run.
+/// The mutating routes reject them, clients that send no `Origin` (not browsers) are not affected
+pub async fn require_mutating_origin(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(origin) = request.headers().get(ORIGIN) {
+        let allowed = state
+            .config
+            .cors
+            .mutating_origins
+            .iter()
+            .any(|allowed| allowed.as_bytes() == origin.as_bytes());
+        if !allowed {
+            return Err(ApiError::Forbidden(format!(
+                "origin {:?} cannot call this route",
+                origin
+            )));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// The config is validated at startup, invalid values can't show up here
+fn cors_layer<'a>(config: &CorsConfig, origins: impl Iterator<Item = &'a String>) -> CorsLayer {
+    let origins: Vec<&String> = origins.collect();
+    let allow_origin = if origins.iter().any(|origin| *origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins
+                .into_iter()
+                .filter_map(|origin| origin.parse::<HeaderValue>().ok()),
+        )
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(
+            config
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse::<Method>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .allow_headers(
+            config
+                .allowed_headers
+                .iter()
+                .filter_map(|header| header.parse::<HeaderName>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .expose_headers(
+            EXPOSED_HEADERS
+                .into_iter()
+                .map(HeaderName::from_static)
+                .chain([REQUEST_ID_HEADER, DEPRECATION_HEADER])
+                .collect::<Vec<_>>(),
+        )
+        .allow_credentials(config.allow_credentials)
+        .max_age(Duration::from_secs(config.max_age_secs))
+}
@@ -1,57 +1,65 @@
+use chrono::Utc;
 use cosmos_sdk_proto::cosmos::base::abci::v1beta1::TxResponse;
-use entities::{events_tx, prelude::*};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use entities::{events_tx, fee_grant, prelude::*, service_flag};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QueryTrait, Set,
+};
 use std::str::from_utf8;
 
-use crate::{error::ApiError, AXL_USDC_DENOM};
+use crate::{error::ApiError, types::txs::IndexedDeposit};
+
+/// Grant ledger statuses
+pub const GRANT_STATUS_ACTIVE: &str = "active";
+pub const GRANT_STATUS_REVOKED: &str = "revoked";
+
+/// Set by the admins to stop granting fees, e.g. when the granter is abused
+pub const FLAG_GRANTING_PAUSED: &str = "granting_paused";
+
+/// How long a fee grant request is left to its handler before another instance resumes it
+pub const FEE_GRANT_REQUEST_LEASE_SECS: i64 = 300;
 
 pub fn events_key(events: Vec<String>) -> String {
     events.concat()
 }
 
+/// Saves the txs and returns the deposits found among them, in one of the `deposit_denoms`.
+/// Txs already saved for the address only get their deposit fields updated
+#[tracing::instrument(skip(new_txs, deposit_denoms, db), fields(txs = new_txs.len()))]
 pub async fn add_txs_to_db(
     address: String,
     new_txs: Vec<TxResponse>,
+    deposit_denoms: &[String],
     db: &DatabaseConnection,
-) -> Result<(), ApiError> {
+) -> Result<Vec<IndexedDeposit>, ApiError> {
     if new_txs.is_empty() {
-        return Ok(());
+        return Ok(vec![]);
     }
     let txhashes = new_txs
         .iter()
         .map(|tx| tx.txhash.clone())
         .collect::<Vec<_>>();
-    log::info!("to save {:?}", txhashes);
+    tracing::info!(?txhashes, "Saving txs");
 
     let txs = new_txs
         .into_iter()
         .map(|tx| {
-            // We look for events related to incoming USDC-ibc transactions to set the tx flags
+            // We look for events related to incoming ibc transactions to set the tx flags
 
-            // The incoming denom should be USDC
-            let is_usdc = tx
+            // The incoming denom should be one of the deposit denoms
+            let deposit_denom = tx
                 .events
                 .iter()
                 .find(|e| e.r#type == "denomination_trace")
-                .map(|e| {
-                    println!(
-                        "{:?}",
-                        e.attributes
-                            .iter()
-                            .find(|a| a.key == "denom")
-                            .map(|a| from_utf8(a.value.as_ref()))
-                    );
-                    e.attributes
-                        .iter()
-                        .find(|a| a.key == "denom")
-                        .map(|a| from_utf8(a.value.as_ref()))
-                        == Some(Ok(AXL_USDC_DENOM))
-                })
-                == Some(true);
-
-            println!("is_usdc, {}", is_usdc);
-
-            let amount = if is_usdc {
+                .and_then(|e| e.attributes.iter().find(|a| a.key == "denom"))
+                .and_then(|a| from_utf8(a.value.as_ref()).ok())
+                .and_then(|denom| deposit_denoms.iter().find(|d| *d == denom))
+                .cloned();
+
+            tracing::debug!(tx_hash = %tx.txhash, ?deposit_denom, "Classified tx");
+
+            let amount = if deposit_denom.is_some() {
                 // An amount has to be set (it's safe because we are querying events based on this event type)
                 tx.events
                     .iter()
@@ -67,20 +75,62 @@ pub async fn add_txs_to_db(
                 None
             };
 
-            Ok(events_tx::ActiveModel {
-                address: Set(address.clone()),
-                tx_hash: Set(tx.txhash),
-                tx_events: Set(tx.logs.into()),
-                timestamp: Set(tx.timestamp),
-                kado_amount: Set(amount),
-                ..Default::default()
-            })
+            // Address that sent the packet on the source chain
+            let sender = tx
+                .events
+                .iter()
+                .find(|e| e.r#type == "fungible_token_packet")
+                .and_then(|e| e.attributes.iter().find(|a| a.key == "sender"))
+                .map(|a| from_utf8(a.value.as_ref()))
+                .transpose()?
+                .map(|s| s.to_string());
+
+            let deposit = amount
+                .clone()
+                .zip(deposit_denom.clone())
+                .map(|(amount, denom)| IndexedDeposit {
+                    tx_hash: tx.txhash.clone(),
+                    denom,
+                    amount,
+                    timestamp: tx.timestamp.clone(),
+                });
+
+            Ok((
+                events_tx::ActiveModel {
+                    address: Set(address.clone()),
+                    tx_hash: Set(tx.txhash),
+                    tx_events: Set(tx.logs.into()),
+                    timestamp: Set(tx.timestamp),
+                    kado_amount: Set(amount),
+                    denom: Set(deposit_denom),
+                    sender: Set(sender),
+                    ..Default::default()
+                },
+                deposit,
+            ))
         })
         .collect::<Result<Vec<_>, ApiError>>()?;
 
-    EventsTx::insert_many(txs).exec(db).await?;
+    let (txs, deposits): (Vec<_>, Vec<_>) = txs.into_iter().unzip();
+    // The indexer and the backfills can save the same txs, the flags set since are kept
+    EventsTx::insert_many(txs)
+        .on_conflict(
+            OnConflict::columns([events_tx::Column::Address, events_tx::Column::TxHash])
+                .update_columns([
+                    events_tx::Column::KadoAmount,
+                    events_tx::Column::Denom,
+                    events_tx::Column::Sender,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
 
-    Ok(())
+    Ok(deposits.into_iter().flatten().collect())
+}
+
+pub fn unknown_deposit(address: &str, txhash: &str) -> ApiError {
+    ApiError::InvalidQuery(format!("no deposit {} for {}", txhash, address))
 }
 
 pub async fn has_had_fee_grant(
@@ -89,26 +139,113 @@ pub async fn has_had_fee_grant(
     db: &DatabaseConnection,
 ) -> Result<(), ApiError> {
     let existing_tx = events_tx::Entity::find()
-        .filter(events_tx::Column::Address.eq(grantee))
-        .filter(events_tx::Column::TxHash.eq(txhash))
+        .filter(events_tx::Column::Address.eq(&grantee))
+        .filter(events_tx::Column::TxHash.eq(&txhash))
         .one(db)
-        .await?;
+        .await?
+        .ok_or_else(|| unknown_deposit(&grantee, &txhash))?;
 
     // Into ActiveModel
-    let mut existing_tx: events_tx::ActiveModel = existing_tx.unwrap().into();
+    let mut existing_tx: events_tx::ActiveModel = existing_tx.into();
 
     // Update name attribute
     existing_tx.has_fee_grant = Set(1);
+    existing_tx.fee_grant_requested_at = Set(None);
     existing_tx.update(db).await?;
 
     Ok(())
 }
 
-pub async fn tx_was_deposited(
+/// Saves that a fee grant was requested for the deposit, until it's confirmed or it fails.
+/// Requests left by a stopped process are resumed on the next start
+pub async fn set_fee_grant_requested(
     grantee: String,
     txhash: String,
+    requested: bool,
     db: &DatabaseConnection,
 ) -> Result<(), ApiError> {
+    EventsTx::update_many()
+        .col_expr(
+            events_tx::Column::FeeGrantRequestedAt,
+            Expr::value(requested.then(Utc::now)),
+        )
+        .filter(events_tx::Column::Address.eq(grantee))
+        .filter(events_tx::Column::TxHash.eq(txhash))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+fn fee_grant_request_stale_before() -> chrono::DateTime<Utc> {
+    Utc::now() - chrono::Duration::seconds(FEE_GRANT_REQUEST_LEASE_SECS)
+}
+
+/// Deposits with a fee grant request that was never confirmed and whose lease expired
+pub async fn pending_fee_grant_requests(
+    db: &DatabaseConnection,
+) -> Result<Vec<events_tx::Model>, ApiError> {
+    Ok(EventsTx::find()
+        .filter(events_tx::Column::FeeGrantRequestedAt.lt(fee_grant_request_stale_before()))
+        .filter(events_tx::Column::HasFeeGrant.eq(0))
+        .all(db)
+        .await?)
+}
+
+/// Takes over a stale fee grant request by renewing its lease.
+/// False when the handler or another instance holds it
+pub async fn claim_fee_grant_request(
+    grantee: String,
+    txhash: String,
+    db: &DatabaseConnection,
+) -> Result<bool, ApiError> {
+    let result = EventsTx::update_many()
+        .col_expr(
+            events_tx::Column::FeeGrantRequestedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(events_tx::Column::Address.eq(grantee))
+        .filter(events_tx::Column::TxHash.eq(txhash))
+        .filter(events_tx::Column::HasFeeGrant.eq(0))
+        .filter(events_tx::Column::FeeGrantRequestedAt.lt(fee_grant_request_stale_before()))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Deposits that were not executed yet, oldest first
+pub async fn pending_deposits(
+    address: Option<String>,
+    db: &DatabaseConnection,
+) -> Result<Vec<events_tx::Model>, ApiError> {
+    Ok(EventsTx::find()
+        .filter(events_tx::Column::KadoAmount.is_not_null())
+        .filter(events_tx::Column::Executed.eq(0))
+        .apply_if(address, |q, address| {
+            q.filter(events_tx::Column::Address.eq(address))
+        })
+        .order_by_asc(events_tx::Column::Timestamp)
+        .all(db)
+        .await?)
+}
+
+/// Marks the tx as executed. Returns whether the tx was found
+pub async fn tx_was_deposited(
+    grantee: String,
+    txhash: String,
+    db: &DatabaseConnection,
+) -> Result<bool, ApiError> {
+    set_tx_executed(grantee, txhash, true, db).await
+}
+
+/// Sets the executed flag of the tx. Returns whether the tx was found
+pub async fn set_tx_executed(
+    grantee: String,
+    txhash: String,
+    executed: bool,
+    db: &DatabaseConnection,
+) -> Result<bool, ApiError> {
     let existing_tx = events_tx::Entity::find()
         .filter(events_tx::Column::Address.eq(grantee))
         .filter(events_tx::Column::TxHash.eq(txhash))
@@ -116,15 +253,91 @@ pub async fn tx_was_deposited(
         .await?;
 
     if existing_tx.is_none() {
-        return Ok(());
+        return Ok(false);
     }
 
     // Into ActiveModel
     let mut existing_tx: events_tx::ActiveModel = existing_tx.unwrap().into();
 
     // Update name attribute
-    existing_tx.executed = Set(1);
+    existing_tx.executed = Set(executed.into());
     existing_tx.update(db).await?;
 
+    Ok(true)
+}
+
+/// Records a new allowance in the grant ledger, replacing the active one if any
+pub async fn record_grant(
+    grantee: String,
+    spend_limit: String,
+    tx_hash: String,
+    db: &DatabaseConnection,
+) -> Result<(), ApiError> {
+    // Re-granting revokes the previous allowance in the same tx
+    record_revocation(vec![grantee.clone()], tx_hash.clone(), db).await?;
+
+    fee_grant::ActiveModel {
+        grantee: Set(grantee),
+        spend_limit: Set(spend_limit),
+        status: Set(GRANT_STATUS_ACTIVE.to_string()),
+        grant_tx_hash: Set(Some(tx_hash)),
+        granted_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the active allowances of the grantees as revoked in the grant ledger
+pub async fn record_revocation(
+    grantees: Vec<String>,
+    tx_hash: String,
+    db: &DatabaseConnection,
+) -> Result<(), ApiError> {
+    FeeGrant::update_many()
+        .col_expr(fee_grant::Column::Status, Expr::value(GRANT_STATUS_REVOKED))
+        .col_expr(fee_grant::Column::RevokeTxHash, Expr::value(tx_hash))
+        .col_expr(fee_grant::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(fee_grant::Column::Grantee.is_in(grantees))
+        .filter(fee_grant::Column::Status.eq(GRANT_STATUS_ACTIVE))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Flags are shared by all the replicas and survive restarts, a flag that was never set is off
+pub async fn service_flag(db: &DatabaseConnection, name: &str) -> Result<bool, ApiError> {
+    Ok(ServiceFlag::find_by_id(name)
+        .one(db)
+        .await?
+        .is_some_and(|flag| flag.enabled != 0))
+}
+
+pub async fn set_service_flag(
+    db: &DatabaseConnection,
+    name: &str,
+    enabled: bool,
+    updated_by: &str,
+) -> Result<(), ApiError> {
+    ServiceFlag::insert(service_flag::ActiveModel {
+        name: Set(name.to_string()),
+        enabled: Set(enabled.into()),
+        updated_by: Set(updated_by.to_string()),
+        updated_at: Set(Utc::now()),
+    })
+    .on_conflict(
+        OnConflict::column(service_flag::Column::Name)
+            .update_columns([
+                service_flag::Column::Enabled,
+                service_flag::Column::UpdatedBy,
+                service_flag::Column::UpdatedAt,
+            ])
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
     Ok(())
 }
@@ -1,10 +1,10 @@
-use std::env::VarError;
+use std::{env::VarError, time::Duration};
 
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use cw_orch::daemon::DaemonError;
 use redis::RedisError;
 use sea_orm::DbErr;
@@ -39,9 +39,54 @@ pub enum ApiError {
     #[error(transparent)]
     StdError(#[from] StdError),
 
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+
     #[error(transparent)]
     Utf6Error(#[from] std::str::Utf8Error),
 
+    #[error(transparent)]
+    PrometheusError(#[from] prometheus::Error),
+
+    #[error("Invalid query : {0}")]
+    InvalidQuery(String),
+
+    #[error("Invalid config : {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid payload : {0}")]
+    InvalidPayload(String),
+
+    #[error("Unauthorized : {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden : {0}")]
+    Forbidden(String),
+
+    #[error("Fee grant rejected : {0}")]
+    GrantRejected(String),
+
+    #[error("Conflict : {0}")]
+    Conflict(String),
+
+    #[error("Fee granting is paused")]
+    GrantingPaused,
+
+    #[error("Tx simulation failed : {0}")]
+    SimulationFailed(String),
+
+    #[error("Account sequence mismatch : {0}")]
+    SequenceMismatch(String),
+
+    #[error("Granter cannot pay the tx fee : {required} needed, {available} available")]
+    InsufficientGranterFunds {
+        required: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Too many requests, retry in {0:?}")]
+    RateLimited(Duration),
+
     #[error("Generic Error : {0}")]
     GenericErr(String),
 }
@@ -50,10 +95,25 @@ pub type ApiResult<T = ()> = Result<T, ApiError>;
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {:?}", self),
-        )
-            .into_response()
+        let status = match self {
+            ApiError::InvalidQuery(_) | ApiError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) | ApiError::GrantRejected(_) => StatusCode::FORBIDDEN,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::GrantingPaused | ApiError::InsufficientGranterFunds { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut response = (status, format!("Something went wrong: {:?}", self)).into_response();
+
+        if let ApiError::RateLimited(retry_after) = self {
+            let retry_after_secs = retry_after.as_secs_f64().ceil() as u64;
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs.max(1)));
+        }
+        response
     }
 }
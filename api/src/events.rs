@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{Stream, StreamExt};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::ApiError;
+use crate::tx_indexer::run_indexer;
+use crate::types::txs::IndexedDeposit;
+use crate::AppState;
+
+/// Number of events a slow subscriber can lag behind before missing some
+const EVENT_BUS_CAPACITY: usize = 1024;
+/// How often the indexer runs for an address with open event streams
+const STREAM_INDEX_INTERVAL: Duration = Duration::from_secs(5);
+/// Suggested wait before opening a stream again once the limit is reached
+const STREAM_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// Redis channel relaying the events between the replicas
+const EVENT_CHANNEL: &str = "address_events";
+/// Wait before subscribing to Redis again after the subscription failed
+const RELAY_RETRY: Duration = Duration::from_secs(5);
+
+/// Status change of the onboarding of an address
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum AddressEvent {
+    DepositDetected {
+        address: String,
+        tx_hash: String,
+        denom: String,
+        amount: String,
+        timestamp: String,
+    },
+    FeeGrantPending {
+        address: String,
+        tx_hash: String,
+    },
+    FeeGrantConfirmed {
+        address: String,
+        tx_hash: String,
+    },
+    FeeGrantFailed {
+        address: String,
+        tx_hash: String,
+        error: String,
+    },
+    DepositExecuted {
+        address: String,
+        tx_hash: String,
+    },
+}
+
+impl AddressEvent {
+    pub fn deposit_detected(address: String, deposit: IndexedDeposit) -> Self {
+        AddressEvent::DepositDetected {
+            address,
+            tx_hash: deposit.tx_hash,
+            denom: deposit.denom,
+            amount: deposit.amount,
+            timestamp: deposit.timestamp,
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        match self {
+            AddressEvent::DepositDetected { address, .. }
+            | AddressEvent::FeeGrantPending { address, .. }
+            | AddressEvent::FeeGrantConfirmed { address, .. }
+            | AddressEvent::FeeGrantFailed { address, .. }
+            | AddressEvent::DepositExecuted { address, .. } => address,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressEvent::DepositDetected { .. } => "deposit_detected",
+            AddressEvent::FeeGrantPending { .. } => "fee_grant_pending",
+            AddressEvent::FeeGrantConfirmed { .. } => "fee_grant_confirmed",
+            AddressEvent::FeeGrantFailed { .. } => "fee_grant_failed",
+            AddressEvent::DepositExecuted { .. } => "deposit_executed",
+        }
+    }
+}
+
+/// Fan-out of the address events.
+/// Webhooks only get the events published on this replica, so they are sent once.
+/// Streams get the events of every replica, relayed through Redis when it is used
+pub struct EventBus {
+    local: broadcast::Sender<AddressEvent>,
+    streamed: broadcast::Sender<AddressEvent>,
+    relay: Option<mpsc::UnboundedSender<AddressEvent>>,
+    streams: Mutex<OpenStreams>,
+}
+
+/// Event streams open on this replica
+#[derive(Default)]
+struct OpenStreams {
+    by_ip: HashMap<IpAddr, usize>,
+    /// Streams of each address, with the token stopping its indexer
+    by_address: HashMap<String, (usize, CancellationToken)>,
+}
+
+impl EventBus {
+    /// With `relayed`, the events to stream are returned to be published on Redis
+    /// by [`spawn_event_relay`]
+    pub fn new(relayed: bool) -> (Self, Option<mpsc::UnboundedReceiver<AddressEvent>>) {
+        let (local, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        let (streamed, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        let (relay, outbox) = relayed.then(mpsc::unbounded_channel).unzip();
+        let bus = Self {
+            local,
+            streamed,
+            relay,
+            streams: Default::default(),
+        };
+        (bus, outbox)
+    }
+
+    pub fn publish(&self, event: AddressEvent) {
+        tracing::debug!("Publishing {} for {}", event.name(), event.address());
+        // Sending only fails when nobody is listening, which is fine
+        let _ = self.local.send(event.clone());
+        match &self.relay {
+            Some(relay) => {
+                if let Err(mpsc::error::SendError(event)) = relay.send(event) {
+                    self.stream(event);
+                }
+            }
+            None => self.stream(event),
+        }
+    }
+
+    /// Events published on this replica
+    pub fn subscribe(&self) -> broadcast::Receiver<AddressEvent> {
+        self.local.subscribe()
+    }
+
+    /// Events published on every replica
+    pub fn subscribe_streamed(&self) -> broadcast::Receiver<AddressEvent> {
+        self.streamed.subscribe()
+    }
+
+    fn stream(&self, event: AddressEvent) {
+        let _ = self.streamed.send(event);
+    }
+}
+
+/// Publishes the events of this replica on Redis, and streams the events of every replica.
+/// Events that could not be published are only streamed here
+pub fn spawn_event_relay(state: Arc<AppState>, mut outbox: mpsc::UnboundedReceiver<AddressEvent>) {
+    let publisher = state.clone();
+    state.tasks.spawn(async move {
+        let Some(mut redis) = publisher.cache.redis() else {
+            return;
+        };
+        loop {
+            let event = tokio::select! {
+                _ = publisher.shutdown.cancelled() => return,
+                event = outbox.recv() => match event {
+                    Some(event) => event,
+                    None => return,
+                },
+            };
+            let published = match serde_json::to_string(&event) {
+                Ok(payload) => redis
+                    .publish::<_, _, ()>(EVENT_CHANNEL, payload)
+                    .await
+                    .map_err(ApiError::from),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = published {
+                tracing::warn!(
+                    "Could not relay {}, streaming it here only: {}",
+                    event.name(),
+                    e
+                );
+                publisher.events.stream(event);
+            }
+        }
+    });
+
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        loop {
+            if let Err(e) = stream_relayed_events(&state).await {
+                tracing::error!("Could not receive the events of the other replicas: {}", e);
+            }
+            tokio::select! {
+                _ = state.shutdown.cancelled() => return,
+                _ = tokio::time::sleep(RELAY_RETRY) => {}
+            }
+        }
+    });
+}
+
+/// Streams the events published on Redis until the subscription ends
+async fn stream_relayed_events(state: &AppState) -> Result<(), ApiError> {
+    let Some(redis_url) = &state.config.redis.url else {
+        return Ok(());
+    };
+    let mut pubsub = redis::Client::open(redis_url.as_str())?
+        .get_async_connection()
+        .await?
+        .into_pubsub();
+    pubsub.subscribe(EVENT_CHANNEL).await?;
+    let mut messages = pubsub.on_message();
+    loop {
+        let message = tokio::select! {
+            _ = state.shutdown.cancelled() => return Ok(()),
+            message = messages.next() => message,
+        };
+        let Some(message) = message else {
+            tracing::warn!("Redis closed the event subscription");
+            return Ok(());
+        };
+        let event = message
+            .get_payload::<String>()
+            .map_err(ApiError::from)
+            .and_then(|payload| Ok(serde_json::from_str::<AddressEvent>(&payload)?));
+        match event {
+            Ok(event) => state.events.stream(event),
+            Err(e) => tracing::error!("Could not read a relayed event: {}", e),
+        }
+    }
+}
+
+/// Registration of an open event stream, released when the stream is dropped
+struct StreamGuard {
+    state: Arc<AppState>,
+    ip: IpAddr,
+    address: String,
+}
+
+impl StreamGuard {
+    /// Registers a stream, starting the indexer of the address for its first stream
+    fn open(state: Arc<AppState>, ip: IpAddr, address: String) -> Result<Self, ApiError> {
+        let limits = &state.config.rate_limit;
+        let mut streams = state.events.streams.lock().unwrap();
+        let ip_streams = streams.by_ip.get(&ip).copied().unwrap_or(0);
+        let address_streams = streams
+            .by_address
+            .get(&address)
+            .map_or(0, |(count, _)| *count);
+        if ip_streams >= limits.streams_per_ip || address_streams >= limits.streams_per_address {
+            tracing::info!("Too many event streams for {} from {}", address, ip);
+            return Err(ApiError::RateLimited(STREAM_RETRY_AFTER));
+        }
+
+        *streams.by_ip.entry(ip).or_default() += 1;
+        match streams.by_address.get_mut(&address) {
+            Some((count, _)) => *count += 1,
+            None => {
+                let stop = state.shutdown.child_token();
+                spawn_stream_indexer(state.clone(), address.clone(), stop.clone());
+                streams.by_address.insert(address.clone(), (1, stop));
+            }
+        }
+        drop(streams);
+
+        Ok(Self { state, ip, address })
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut streams = self.state.events.streams.lock().unwrap();
+        if let Some(count) = streams.by_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                streams.by_ip.remove(&self.ip);
+            }
+        }
+        if let Some((count, stop)) = streams.by_address.get_mut(&self.address) {
+            *count -= 1;
+            if *count == 0 {
+                stop.cancel();
+                streams.by_address.remove(&self.address);
+            }
+        }
+    }
+}
+
+/// Indexes an address periodically until its last stream is closed.
+/// New deposits are published on the bus, which feeds every stream of the address on every replica
+fn spawn_stream_indexer(state: Arc<AppState>, address: String, stop: CancellationToken) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        let mut interval = tokio::time::interval(STREAM_INDEX_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = interval.tick() => {
+                    if let Err(e) = run_indexer(state.clone(), address.clone()).await {
+                        tracing::warn!("Indexing {} for its event streams failed: {}", address, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Streams the events of an address as Server-Sent Events.
+/// While streams are open, the indexer runs periodically for their address.
+/// Streams are limited per client IP and per address
+#[utoipa::path(
+    get,
+    path = "/v1/address/{address}/events",
+    tag = "addresses",
+    params(("address" = String, Path)),
+    responses(
+        (
+            status = 200,
+            description = "Events named after their type, with a JSON payload",
+            content_type = "text/event-stream"
+        ),
+        (status = 429, description = "Too many open streams", body = String)
+    )
+)]
+pub async fn address_events(
+    Path(address): Path<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let ip = state.rate_limiter.client_ip(peer.ip(), &headers);
+    let receiver = state.events.subscribe_streamed();
+    let guard = StreamGuard::open(state, ip, address)?;
+
+    let stream = futures::stream::unfold((receiver, guard), |(mut receiver, guard)| async move {
+        let shutdown = guard.state.shutdown.clone();
+        let address = guard.address.clone();
+        loop {
+            tokio::select! {
+                // Open streams would otherwise keep the server from shutting down
+                _ = shutdown.cancelled() => return None,
+                received = receiver.recv() => match received {
+                    Ok(event) if event.address() == address => {
+                        match Event::default().event(event.name()).json_data(&event) {
+                            Ok(sse_event) => return Some((Ok(sse_event), (receiver, guard))),
+                            Err(e) => tracing::error!("Could not serialize {:?}: {}", event, e),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Event stream of {} skipped {} events", address, skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -1,75 +1,299 @@
+use crate::cache::fee_grant_key;
+use crate::config::FeePolicy;
+use crate::db_helpers::{record_grant, record_revocation};
 use crate::error::ApiError;
-use crate::types::grants::{BasicAllowanceGrant, QuerierGrant};
+use crate::gas::gas_price;
+use crate::grpc::GrpcChannel;
+use crate::types::grants::{
+    BasicAllowanceGrant, GrantSimulationResult, PeriodicAllowanceGrant, PeriodicReset, QuerierGrant,
+};
+use chrono::{DateTime, Utc};
+use cosmos_sdk_proto::cosmos::bank::v1beta1::query_client::QueryClient as BankQueryClient;
+use cosmos_sdk_proto::cosmos::bank::v1beta1::QueryBalanceRequest;
+use cosmos_sdk_proto::cosmos::base::query::v1beta1::PageRequest;
 use cosmos_sdk_proto::cosmos::base::v1beta1::Coin;
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::query_client::QueryClient as FeegrantQueryClient;
 use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{
-    BasicAllowance, MsgGrantAllowance, MsgRevokeAllowance,
+    BasicAllowance, Grant, MsgGrantAllowance, MsgRevokeAllowance, PeriodicAllowance,
+    QueryAllowanceRequest, QueryAllowancesByGranterRequest,
 };
 use cosmos_sdk_proto::traits::{Message, Name};
 use cosmos_sdk_proto::Any;
-use cw_orch::daemon::queriers::{DaemonQuerier, Feegrant};
-use cw_orch::daemon::DaemonAsync;
-use tokio::sync::MutexGuard;
-use tonic::transport::Channel;
+use cosmwasm_std::{Coin as CwCoin, Uint128};
+use std::str::FromStr;
+
+use crate::AppState;
 
-const FEE_DENOM: &str = "uluna";
-const FEE_GRANT_AMOUNT: u128 = 100_000;
-const MIN_FEE_GRANT_AMOUNT: u128 = 20_000;
+const GRANTS_PAGE_SIZE: u64 = 100;
+
+/// Balance of an address in a single denom
+pub async fn query_balance(
+    chain: GrpcChannel,
+    address: String,
+    denom: &str,
+) -> Result<Uint128, ApiError> {
+    let balance = BankQueryClient::new(chain)
+        .balance(QueryBalanceRequest {
+            address,
+            denom: denom.to_string(),
+        })
+        .await?
+        .into_inner()
+        .balance;
+
+    Ok(balance
+        .map(|coin| Uint128::from_str(&coin.amount))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Allowance of the grantee as the node returns it, still encoded
+pub async fn query_allowance(
+    chain: GrpcChannel,
+    granter: String,
+    grantee: String,
+) -> Option<Grant> {
+    // A missing allowance is returned as an error
+    FeegrantQueryClient::new(chain)
+        .allowance(QueryAllowanceRequest { granter, grantee })
+        .await
+        .ok()
+        .and_then(|response| response.into_inner().allowance)
+}
 
 pub async fn get_current_fee_grants(
-    chain: Channel,
+    chain: GrpcChannel,
     granter: String,
     grantee: String,
 ) -> Result<Option<QuerierGrant>, ApiError> {
-    let fee_grant = Feegrant::new(chain.clone());
-    let grant = fee_grant.allowance(granter, grantee).await.ok();
+    let grant = query_allowance(chain, granter, grantee).await;
 
-    // We try to decode the grant basic allowance
-    let basic_allowance = grant
+    // We try to decode the allowance types we know about
+    let decoded_grant = grant
         .map(|g| {
-            match g
-                .allowance
-                .as_ref()
-                .map(Any::to_msg::<BasicAllowance>)
-                .transpose()
-            {
-                Ok(basic_allowance) => {
-                    Ok::<_, ApiError>(QuerierGrant::BasicAllowance(BasicAllowanceGrant {
-                        granter: g.granter,
-                        grantee: g.grantee,
-                        allowance: basic_allowance.map(TryInto::try_into).transpose()?,
-                    }))
-                }
-                Err(_) => Ok(QuerierGrant::AnyAllowance(g.into())),
+            let allowance = g.allowance.as_ref();
+            if let Some(Ok(basic)) = allowance.map(Any::to_msg::<BasicAllowance>) {
+                return Ok::<_, ApiError>(QuerierGrant::BasicAllowance(BasicAllowanceGrant {
+                    granter: g.granter,
+                    grantee: g.grantee,
+                    allowance: Some(basic.try_into()?),
+                }));
+            }
+            if let Some(Ok(periodic)) = allowance.map(Any::to_msg::<PeriodicAllowance>) {
+                return Ok(QuerierGrant::PeriodicAllowance(PeriodicAllowanceGrant {
+                    granter: g.granter,
+                    grantee: g.grantee,
+                    allowance: periodic.try_into()?,
+                }));
+            }
+            if allowance.is_none() {
+                return Ok(QuerierGrant::BasicAllowance(BasicAllowanceGrant {
+                    granter: g.granter,
+                    grantee: g.grantee,
+                    allowance: None,
+                }));
             }
+            Ok(QuerierGrant::AnyAllowance(g.into()))
         })
         .transpose()?;
 
-    Ok(basic_allowance)
+    Ok(decoded_grant)
 }
 
-pub async fn grant(
-    daemon: &MutexGuard<'_, DaemonAsync>,
-    grantee: String,
-) -> Result<String, ApiError> {
-    let granter = daemon.sender().to_string();
-    // Check the existing fee grants this address has
-    let existing_grants =
-        get_current_fee_grants(daemon.channel().clone(), granter.clone(), grantee.clone()).await?;
+/// Empty spend limits mean there is no limit
+fn limited(coins: &[CwCoin]) -> Option<Vec<CwCoin>> {
+    (!coins.is_empty()).then(|| coins.to_vec())
+}
 
-    // We don't set a grant if there's already a grant and if it's sufficient
-    if let Some(QuerierGrant::BasicAllowance(allowance)) = &existing_grants {
-        if let Some(allowance) = &allowance.allowance {
-            let amount = allowance
-                .spend_limit
+/// What the grantee can spend right now, `None` when unlimited or unknown
+pub fn remaining_allowance(grant: &QuerierGrant, now: DateTime<Utc>) -> Option<Vec<CwCoin>> {
+    match grant {
+        QuerierGrant::BasicAllowance(grant) => grant
+            .allowance
+            .as_ref()
+            .and_then(|allowance| limited(&allowance.spend_limit)),
+        QuerierGrant::PeriodicAllowance(grant) => {
+            let allowance = &grant.allowance;
+            // The period amount is refilled on the next spend once the reset time is passed
+            let period = if allowance.period_reset.is_some_and(|reset| reset <= now) {
+                limited(&allowance.period_spend_limit)
+            } else {
+                limited(&allowance.period_can_spend)
+            };
+            let basic = allowance
+                .basic
+                .as_ref()
+                .and_then(|basic| limited(&basic.spend_limit));
+            match (basic, period) {
+                (Some(basic), Some(period)) => Some(
+                    basic
+                        .into_iter()
+                        .filter_map(|coin| {
+                            let period_coin = period.iter().find(|c| c.denom == coin.denom)?;
+                            Some(CwCoin {
+                                amount: coin.amount.min(period_coin.amount),
+                                denom: coin.denom,
+                            })
+                        })
+                        .collect(),
+                ),
+                (basic, period) => basic.or(period),
+            }
+        }
+        QuerierGrant::AnyAllowance(_) => None,
+    }
+}
+
+pub fn allowance_expiration(grant: &QuerierGrant) -> Option<DateTime<Utc>> {
+    match grant {
+        QuerierGrant::BasicAllowance(grant) => grant.allowance.as_ref()?.expiration,
+        QuerierGrant::PeriodicAllowance(grant) => grant.allowance.basic.as_ref()?.expiration,
+        QuerierGrant::AnyAllowance(_) => None,
+    }
+}
+
+/// Whether [`grant`] replaces the existing allowance: it's missing, expired, unknown or too low
+pub fn would_regrant(
+    existing: Option<&QuerierGrant>,
+    now: DateTime<Utc>,
+    fees: &FeePolicy,
+) -> bool {
+    let Some(existing) = existing else {
+        return true;
+    };
+    if allowance_expiration(existing).is_some_and(|expiration| expiration <= now) {
+        return true;
+    }
+    match existing {
+        QuerierGrant::AnyAllowance(_)
+        | QuerierGrant::BasicAllowance(BasicAllowanceGrant {
+            allowance: None, ..
+        }) => true,
+        _ => remaining_allowance(existing, now).is_some_and(|remaining| {
+            let amount = remaining
                 .iter()
-                .find(|c| c.denom == FEE_DENOM)
+                .find(|c| c.denom == fees.denom)
                 .map(|c| c.amount)
                 .unwrap_or_default();
-            if amount.u128() >= MIN_FEE_GRANT_AMOUNT {
-                return Ok("Already enough allowance for this address".to_string());
-            }
+            amount.u128() < fees.min_grant_amount as u128
+        }),
+    }
+}
+
+/// Number of deposit txs the fee denom amount pays for
+fn estimate_txs_covered(remaining: &[CwCoin], gas_price: f64, fees: &FeePolicy) -> Option<u64> {
+    let fee_per_tx = (fees.deposit_tx_gas as f64 * gas_price).ceil() as u128;
+    if fee_per_tx == 0 {
+        return None;
+    }
+    let amount = remaining
+        .iter()
+        .find(|c| c.denom == fees.denom)
+        .map(|c| c.amount)
+        .unwrap_or_default();
+    Some((amount.u128() / fee_per_tx) as u64)
+}
+
+/// All the allowances given by the granter, walking every page
+pub async fn get_grants_by_granter(
+    chain: GrpcChannel,
+    granter: String,
+) -> Result<Vec<Grant>, ApiError> {
+    let mut client = FeegrantQueryClient::new(chain);
+    let mut grants = vec![];
+    let mut next_key = vec![];
+    loop {
+        let response = client
+            .allowances_by_granter(QueryAllowancesByGranterRequest {
+                granter: granter.clone(),
+                pagination: Some(PageRequest {
+                    key: next_key,
+                    offset: 0,
+                    limit: GRANTS_PAGE_SIZE,
+                    count_total: false,
+                    reverse: false,
+                }),
+            })
+            .await?
+            .into_inner();
+        grants.extend(response.allowances);
+
+        next_key = response.pagination.map(|p| p.next_key).unwrap_or_default();
+        if next_key.is_empty() {
+            return Ok(grants);
         }
     }
+}
+
+pub async fn simulate_grant(
+    chain: GrpcChannel,
+    granter: String,
+    grantee: String,
+    gas_price: f64,
+    fees: &FeePolicy,
+) -> Result<GrantSimulationResult, ApiError> {
+    // Check the existing fee grants this address has
+    let existing_grant = get_current_fee_grants(chain, granter, grantee).await?;
+    let now = Utc::now();
+
+    let remaining = existing_grant
+        .as_ref()
+        .and_then(|grant| remaining_allowance(grant, now));
+    let periodic = match &existing_grant {
+        Some(QuerierGrant::PeriodicAllowance(grant)) => Some(PeriodicReset {
+            period_secs: grant.allowance.period_secs,
+            period_spend_limit: grant.allowance.period_spend_limit.clone(),
+            period_can_spend: grant.allowance.period_can_spend.clone(),
+            period_reset: grant.allowance.period_reset,
+        }),
+        _ => None,
+    };
+
+    Ok(GrantSimulationResult {
+        estimated_txs_covered: remaining
+            .as_ref()
+            .and_then(|remaining| estimate_txs_covered(remaining, gas_price, fees)),
+        expiration: existing_grant.as_ref().and_then(allowance_expiration),
+        would_regrant: would_regrant(existing_grant.as_ref(), now, fees),
+        remaining,
+        periodic,
+        grant: existing_grant,
+    })
+}
+
+/// Same as [`simulate_grant`], for the app granter, cached for a short time
+pub async fn cached_simulate_grant(
+    state: &AppState,
+    grantee: String,
+) -> Result<GrantSimulationResult, ApiError> {
+    state
+        .cache
+        .get_or_insert(
+            &fee_grant_key(&state.sender, &grantee),
+            state.cache.ttls.fee_grant,
+            simulate_grant(
+                state.channel.clone(),
+                state.sender.clone(),
+                grantee.clone(),
+                gas_price(state).await,
+                &state.config.fees,
+            ),
+        )
+        .await
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn grant(state: &AppState, grantee: String) -> Result<String, ApiError> {
+    let granter = state.sender.clone();
+    // Check the existing fee grants this address has
+    let existing_grants =
+        get_current_fee_grants(state.channel.clone(), granter.clone(), grantee.clone()).await?;
+
+    // We don't set a grant if there's already a grant and if it's sufficient
+    let fees = &state.config.fees;
+    if !would_regrant(existing_grants.as_ref(), Utc::now(), fees) {
+        return Ok("Already enough allowance for this address".to_string());
+    }
     let mut msgs = vec![];
     if existing_grants.is_some() {
         msgs.push(Any {
@@ -84,8 +308,8 @@ pub async fn grant(
 
     let allowance_type = BasicAllowance {
         spend_limit: vec![Coin {
-            amount: FEE_GRANT_AMOUNT.to_string(),
-            denom: FEE_DENOM.to_string(),
+            amount: fees.grant_amount.to_string(),
+            denom: fees.denom.clone(),
         }],
         expiration: None,
     };
@@ -104,7 +328,165 @@ pub async fn grant(
         value: fee_grant.encode_to_vec(),
     });
 
-    daemon.sender.commit_tx_any(msgs, None).await?;
+    let response = state.signer.commit(msgs, "grant").await?;
+    // The grant is on chain at this point, failing to record it doesn't fail the grant
+    if let Err(e) = record_grant(
+        grantee.clone(),
+        format!("{}{}", fees.grant_amount, fees.denom),
+        response.txhash.clone(),
+        &state.db,
+    )
+    .await
+    {
+        tracing::error!(
+            "Could not record the grant to {} in {}: {}",
+            grantee,
+            response.txhash,
+            e
+        );
+    }
 
     Ok("Tx succesfully submitted".to_string())
 }
+
+#[tracing::instrument(skip(state))]
+pub async fn revoke(state: &AppState, grantee: String) -> Result<String, ApiError> {
+    let existing_grants =
+        get_current_fee_grants(state.channel.clone(), state.sender.clone(), grantee.clone())
+            .await?;
+
+    if existing_grants.is_none() {
+        return Ok("No allowance to revoke for this address".to_string());
+    }
+
+    revoke_many(state, vec![grantee]).await?;
+
+    Ok("Allowance succesfully revoked".to_string())
+}
+
+/// Revokes the allowances of all the grantees in a single tx, returns the tx hash
+#[tracing::instrument(skip(state))]
+pub async fn revoke_many(state: &AppState, grantees: Vec<String>) -> Result<String, ApiError> {
+    let granter = state.sender.clone();
+    let msgs = grantees
+        .iter()
+        .map(|grantee| Any {
+            type_url: MsgRevokeAllowance::type_url(),
+            value: MsgRevokeAllowance {
+                granter: granter.clone(),
+                grantee: grantee.clone(),
+            }
+            .encode_to_vec(),
+        })
+        .collect();
+
+    let response = state.signer.commit(msgs, "revoke").await?;
+    if let Err(e) = record_revocation(grantees, response.txhash.clone(), &state.db).await {
+        tracing::error!("Could not record the revocation {}: {}", response.txhash, e);
+    }
+
+    Ok(response.txhash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::grants::{CosmosBasicAllowance, CosmosPeriodicAllowance};
+
+    fn coins(amount: u128) -> Vec<CwCoin> {
+        vec![CwCoin {
+            denom: FeePolicy::default().denom,
+            amount: amount.into(),
+        }]
+    }
+
+    fn basic(spend_limit: Vec<CwCoin>, expiration: Option<DateTime<Utc>>) -> QuerierGrant {
+        QuerierGrant::BasicAllowance(BasicAllowanceGrant {
+            granter: "granter".to_string(),
+            grantee: "grantee".to_string(),
+            allowance: Some(CosmosBasicAllowance {
+                spend_limit,
+                expiration,
+            }),
+        })
+    }
+
+    fn periodic(
+        basic_limit: Vec<CwCoin>,
+        can_spend: u128,
+        period_reset: DateTime<Utc>,
+    ) -> QuerierGrant {
+        QuerierGrant::PeriodicAllowance(PeriodicAllowanceGrant {
+            granter: "granter".to_string(),
+            grantee: "grantee".to_string(),
+            allowance: CosmosPeriodicAllowance {
+                basic: Some(CosmosBasicAllowance {
+                    spend_limit: basic_limit,
+                    expiration: None,
+                }),
+                period_secs: 86_400,
+                period_spend_limit: coins(50_000),
+                period_can_spend: coins(can_spend),
+                period_reset: Some(period_reset),
+            },
+        })
+    }
+
+    #[test]
+    fn basic_allowance_remaining_is_its_spend_limit() {
+        let now = Utc::now();
+        assert_eq!(
+            remaining_allowance(&basic(coins(1_000), None), now),
+            Some(coins(1_000))
+        );
+        assert_eq!(remaining_allowance(&basic(vec![], None), now), None);
+    }
+
+    #[test]
+    fn periodic_allowance_remaining_is_capped_by_both_limits() {
+        let now = Utc::now();
+        let later = now + chrono::Duration::hours(1);
+        assert_eq!(
+            remaining_allowance(&periodic(coins(100_000), 30_000, later), now),
+            Some(coins(30_000))
+        );
+        assert_eq!(
+            remaining_allowance(&periodic(coins(10_000), 30_000, later), now),
+            Some(coins(10_000))
+        );
+        // The period is refilled once the reset time is passed
+        let earlier = now - chrono::Duration::hours(1);
+        assert_eq!(
+            remaining_allowance(&periodic(coins(100_000), 0, earlier), now),
+            Some(coins(50_000))
+        );
+        assert_eq!(
+            remaining_allowance(&periodic(vec![], 30_000, later), now),
+            Some(coins(30_000))
+        );
+    }
+
+    #[test]
+    fn missing_or_expired_allowances_are_granted_again() {
+        let now = Utc::now();
+        let fees = FeePolicy::default();
+        assert!(would_regrant(None, now, &fees));
+
+        let expired = basic(coins(100_000), Some(now - chrono::Duration::seconds(1)));
+        assert!(would_regrant(Some(&expired), now, &fees));
+        let valid = basic(coins(100_000), Some(now + chrono::Duration::days(1)));
+        assert!(!would_regrant(Some(&valid), now, &fees));
+    }
+
+    #[test]
+    fn low_allowances_are_granted_again() {
+        let now = Utc::now();
+        let fees = FeePolicy::default();
+        let low = basic(coins(fees.min_grant_amount as u128 - 1), None);
+        assert!(would_regrant(Some(&low), now, &fees));
+        let enough = basic(coins(fees.min_grant_amount as u128), None);
+        assert!(!would_regrant(Some(&enough), now, &fees));
+        // An unlimited allowance is enough
+        assert!(!would_regrant(Some(&basic(vec![], None)), now, &fees));
+    }
+}
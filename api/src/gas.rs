@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use cosmos_sdk_proto::cosmos::auth::v1beta1::query_client::QueryClient as AuthQueryClient;
+use cosmos_sdk_proto::cosmos::auth::v1beta1::{BaseAccount, QueryAccountRequest};
+use cosmos_sdk_proto::cosmos::tx::signing::v1beta1::SignMode;
+use cosmos_sdk_proto::cosmos::tx::v1beta1::service_client::ServiceClient as TxServiceClient;
+use cosmos_sdk_proto::cosmos::tx::v1beta1::{
+    mode_info, AuthInfo, Fee, ModeInfo, SignerInfo, SimulateRequest, Tx, TxBody,
+};
+use cosmos_sdk_proto::traits::Message;
+use cosmos_sdk_proto::Any;
+use cosmwasm_std::Uint128;
+
+use crate::error::ApiError;
+use crate::fee_grants::query_balance;
+use crate::grpc::GrpcChannel;
+use crate::AppState;
+
+const GAS_PRICE_KEY: &str = "gas_price";
+const GAS_PRICE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct FeeEstimate {
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub gas_price: f64,
+    pub fee_amount: u128,
+}
+
+/// Live gas price in the fee denom, or the configured one
+pub async fn gas_price(state: &AppState) -> f64 {
+    let chain = &state.config.chain;
+    // The network default is filled in at startup
+    let default_price = chain.gas_price.unwrap_or_default();
+    let Some(url) = &chain.gas_prices_url else {
+        return default_price;
+    };
+    match state
+        .cache
+        .get_or_insert(
+            GAS_PRICE_KEY,
+            GAS_PRICE_TTL,
+            fetch_gas_price(url, &state.config.fees.denom),
+        )
+        .await
+    {
+        Ok(price) => price,
+        Err(e) => {
+            tracing::warn!(
+                "Could not fetch the gas price, using the default one: {}",
+                e
+            );
+            default_price
+        }
+    }
+}
+
+async fn fetch_gas_price(url: &str, denom: &str) -> Result<f64, ApiError> {
+    let prices = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| ApiError::GenericErr(e.to_string()))?
+        .json::<HashMap<String, String>>()
+        .await
+        .map_err(|e| ApiError::GenericErr(e.to_string()))?;
+
+    prices
+        .get(denom)
+        .and_then(|price| price.parse().ok())
+        .ok_or_else(|| ApiError::GenericErr(format!("No gas price for {}", denom)))
+}
+
+/// Account number, public key and sequence of the address
+pub async fn query_account(channel: GrpcChannel, address: String) -> Result<BaseAccount, ApiError> {
+    let account = AuthQueryClient::new(channel)
+        .account(QueryAccountRequest { address })
+        .await?
+        .into_inner()
+        .account
+        .ok_or_else(|| ApiError::GenericErr("Account not found".to_string()))?;
+
+    Ok(BaseAccount::decode(account.value.as_slice())?)
+}
+
+/// Gas used by the msgs when sent by `account` at its sequence
+///
+/// Signatures are not verified during simulation, so the tx is left unsigned
+pub async fn simulate_gas(
+    channel: GrpcChannel,
+    account: &BaseAccount,
+    msgs: Vec<Any>,
+) -> Result<u64, ApiError> {
+    let tx = Tx {
+        body: Some(TxBody {
+            messages: msgs,
+            ..Default::default()
+        }),
+        auth_info: Some(AuthInfo {
+            signer_infos: vec![SignerInfo {
+                public_key: account.pub_key.clone(),
+                mode_info: Some(ModeInfo {
+                    sum: Some(mode_info::Sum::Single(mode_info::Single {
+                        mode: SignMode::Direct.into(),
+                    })),
+                }),
+                sequence: account.sequence,
+            }],
+            fee: Some(Fee::default()),
+            tip: None,
+        }),
+        signatures: vec![vec![]],
+    };
+
+    #[allow(deprecated)]
+    let simulation = TxServiceClient::new(channel)
+        .simulate(SimulateRequest {
+            tx: None,
+            tx_bytes: tx.encode_to_vec(),
+        })
+        .await
+        .map_err(|status| ApiError::SimulationFailed(status.message().to_string()))?
+        .into_inner();
+
+    Ok(simulation
+        .gas_info
+        .map(|gas_info| gas_info.gas_used)
+        .unwrap_or_default())
+}
+
+/// Simulates the msgs and makes sure the granter can pay for them
+pub async fn estimate_fee(
+    state: &AppState,
+    account: &BaseAccount,
+    msgs: Vec<Any>,
+    kind: &str,
+) -> Result<FeeEstimate, ApiError> {
+    let gas_used = match simulate_gas(state.channel.clone(), account, msgs).await {
+        Ok(gas_used) => gas_used,
+        Err(e) => {
+            state
+                .metrics
+                .tx_simulation_failures
+                .with_label_values(&[kind])
+                .inc();
+            return Err(e);
+        }
+    };
+    let gas_limit = (gas_used as f64 * state.config.chain.gas_multiplier).ceil() as u64;
+    let gas_price = gas_price(state).await;
+    let fee_amount = (gas_limit as f64 * gas_price).ceil() as u128;
+    let estimate = FeeEstimate {
+        gas_used,
+        gas_limit,
+        gas_price,
+        fee_amount,
+    };
+    tracing::info!("Simulated {} tx: {:?}", kind, estimate);
+
+    let metrics = &state.metrics;
+    metrics
+        .tx_gas_used
+        .with_label_values(&[kind])
+        .observe(gas_used as f64);
+    metrics
+        .tx_gas_limit
+        .with_label_values(&[kind])
+        .observe(gas_limit as f64);
+    metrics
+        .tx_fee
+        .with_label_values(&[kind])
+        .observe(fee_amount as f64);
+
+    let balance = query_balance(
+        state.channel.clone(),
+        state.sender.clone(),
+        &state.config.fees.denom,
+    )
+    .await?;
+    if balance < Uint128::new(fee_amount) {
+        return Err(ApiError::InsufficientGranterFunds {
+            required: fee_amount.into(),
+            available: balance,
+        });
+    }
+
+    Ok(estimate)
+}
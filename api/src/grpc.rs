@@ -0,0 +1,72 @@
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use prometheus::{HistogramVec, IntCounterVec};
+use tonic::body::BoxBody;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::transport::{Body, Channel};
+use tracing::Instrument;
+
+use crate::metrics::Metrics;
+
+const GRPC_STATUS_HEADER: &str = "grpc-status";
+
+/// gRPC channel recording the calls made through it, per method
+#[derive(Clone)]
+pub struct GrpcChannel {
+    inner: Channel,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+}
+
+impl GrpcChannel {
+    pub fn new(inner: Channel, metrics: &Metrics) -> Self {
+        Self {
+            inner,
+            requests: metrics.grpc_requests.clone(),
+            duration: metrics.grpc_request_duration.clone(),
+        }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for GrpcChannel {
+    type Response = http::Response<Body>;
+    type Error = tonic::transport::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        // The path is `/{service}/{method}`
+        let method = request.uri().path().trim_start_matches('/').to_string();
+        let requests = self.requests.clone();
+        let duration = self.duration.clone();
+        let start = Instant::now();
+        let response = self.inner.call(request);
+        let span = tracing::debug_span!("grpc", method = %method);
+
+        Box::pin(
+            async move {
+                let response = response.await;
+                // Errors are sent without a body, their status is in the headers
+                let status = match &response {
+                    Ok(response) => response
+                        .headers()
+                        .get(GRPC_STATUS_HEADER)
+                        .and_then(|status| status.to_str().ok())
+                        .unwrap_or("0")
+                        .to_string(),
+                    Err(_) => "transport".to_string(),
+                };
+                requests.with_label_values(&[&method, &status]).inc();
+                duration
+                    .with_label_values(&[&method])
+                    .observe(start.elapsed().as_secs_f64());
+                response
+            }
+            .instrument(span),
+        )
+    }
+}
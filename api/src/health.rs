@@ -0,0 +1,263 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use cosmos_sdk_proto::cosmos::base::tendermint::v1beta1::service_client::ServiceClient as TendermintClient;
+use cosmos_sdk_proto::cosmos::base::tendermint::v1beta1::GetLatestBlockRequest;
+use entities::events_tx;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+
+use crate::error::ApiError;
+use crate::fee_grants::query_balance;
+use crate::types::health::{CheckStatus, DependencyCheck, StatusReport};
+use crate::AppState;
+
+/// Checks taking longer than this are considered down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Liveness: the process answers
+#[utoipa::path(get, path = "/healthz", tag = "health", responses((status = 200, body = String)))]
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness: the database and the node can be reached
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, body = StatusReport),
+        (status = 503, description = "A dependency is down", body = StatusReport)
+    )
+)]
+pub async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<StatusReport>) {
+    let (database, grpc) = futures::join!(check_database(&state), check_grpc(&state));
+    report_with_status(vec![database, grpc])
+}
+
+/// Status of every dependency, with the time each check took.
+/// Served publicly, so the granter balance is only compared to its minimum
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "health",
+    responses(
+        (status = 200, body = StatusReport),
+        (status = 503, description = "A dependency is down", body = StatusReport)
+    )
+)]
+pub async fn status(State(state): State<Arc<AppState>>) -> (StatusCode, Json<StatusReport>) {
+    let (database, grpc, redis, balance, deposit) = futures::join!(
+        check_database(&state),
+        check_grpc(&state),
+        check_redis(&state),
+        check_granter_balance(&state),
+        check_latest_deposit(&state),
+    );
+    report_with_status(vec![database, grpc, redis, balance, deposit])
+}
+
+fn report_with_status(checks: Vec<DependencyCheck>) -> (StatusCode, Json<StatusReport>) {
+    let status = checks
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok)
+        .max(CheckStatus::Ok);
+    let code = if status == CheckStatus::Down {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (code, Json(StatusReport { status, checks }))
+}
+
+/// Times the check, which returns its status and details
+async fn run_check<F>(name: &'static str, check: F) -> DependencyCheck
+where
+    F: Future<Output = Result<(CheckStatus, String), ApiError>>,
+{
+    let start = Instant::now();
+    let (status, details) = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok((status, details))) => (status, details),
+        Ok(Err(e)) => (CheckStatus::Down, e.to_string()),
+        Err(_) => (CheckStatus::Down, "timed out".to_string()),
+    };
+    if status == CheckStatus::Down {
+        tracing::warn!("Health check {} failed: {}", name, details);
+    }
+    DependencyCheck {
+        name,
+        status,
+        latency_ms: start.elapsed().as_millis() as u64,
+        details: Some(details),
+    }
+}
+
+async fn check_database(state: &AppState) -> DependencyCheck {
+    run_check("database", async {
+        state.db.ping().await?;
+        Ok((CheckStatus::Ok, "reachable".to_string()))
+    })
+    .await
+}
+
+async fn check_grpc(state: &AppState) -> DependencyCheck {
+    run_check("grpc", async {
+        let block = TendermintClient::new(state.channel.clone())
+            .get_latest_block(GetLatestBlockRequest {})
+            .await?
+            .into_inner();
+        let height = block
+            .block
+            .and_then(|block| block.header)
+            .map(|header| header.height)
+            .unwrap_or_default();
+        Ok((CheckStatus::Ok, format!("latest height {}", height)))
+    })
+    .await
+}
+
+async fn check_redis(state: &AppState) -> DependencyCheck {
+    run_check("redis", async {
+        let Some(mut connection) = state.cache.redis() else {
+            return Ok((
+                CheckStatus::Disabled,
+                "using the in-process cache".to_string(),
+            ));
+        };
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut connection)
+            .await?;
+        Ok((CheckStatus::Ok, "reachable".to_string()))
+    })
+    .await
+}
+
+async fn check_granter_balance(state: &AppState) -> DependencyCheck {
+    run_check("granter_balance", async {
+        let denom = &state.config.fees.denom;
+        let balance = query_balance(state.channel.clone(), state.sender.clone(), denom).await?;
+        // The status is public, it doesn't tell how much the granter holds
+        Ok(if balance < state.config.min_granter_balance() {
+            (CheckStatus::Degraded, "below the minimum".to_string())
+        } else {
+            (CheckStatus::Ok, "above the minimum".to_string())
+        })
+    })
+    .await
+}
+
+async fn check_latest_deposit(state: &AppState) -> DependencyCheck {
+    run_check("latest_deposit", async {
+        // Timestamps are all RFC 3339 in UTC, their order is the alphabetical order
+        let latest = events_tx::Entity::find()
+            .select_only()
+            .column_as(Expr::col(events_tx::Column::Timestamp).max(), "latest")
+            .filter(events_tx::Column::KadoAmount.is_not_null())
+            .into_tuple::<Option<String>>()
+            .one(&state.db)
+            .await?
+            .flatten();
+        let latest = latest
+            .as_deref()
+            .map(DateTime::parse_from_rfc3339)
+            .transpose()
+            .map_err(|e| ApiError::GenericErr(e.to_string()))?;
+        // Deposits come in continuously, none for a while means the indexing is stuck
+        Ok(match latest {
+            Some(latest) => {
+                let age = Utc::now() - latest.with_timezone(&Utc);
+                let status = if age > state.config.max_deposit_age() {
+                    CheckStatus::Degraded
+                } else {
+                    CheckStatus::Ok
+                };
+                (status, format!("indexed {}s ago", age.num_seconds()))
+            }
+            None => (CheckStatus::Ok, "no deposit indexed".to_string()),
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(status: CheckStatus) -> DependencyCheck {
+        DependencyCheck {
+            name: "check",
+            status,
+            latency_ms: 0,
+            details: None,
+        }
+    }
+
+    fn aggregate(statuses: &[CheckStatus]) -> (StatusCode, CheckStatus) {
+        let (code, Json(report)) =
+            report_with_status(statuses.iter().copied().map(check).collect());
+        (code, report.status)
+    }
+
+    #[test]
+    fn no_checks_is_ok() {
+        assert_eq!(aggregate(&[]), (StatusCode::OK, CheckStatus::Ok));
+    }
+
+    #[test]
+    fn disabled_checks_do_not_degrade_the_report() {
+        assert_eq!(
+            aggregate(&[CheckStatus::Ok, CheckStatus::Disabled]),
+            (StatusCode::OK, CheckStatus::Ok)
+        );
+        assert_eq!(
+            aggregate(&[CheckStatus::Disabled]),
+            (StatusCode::OK, CheckStatus::Ok)
+        );
+        assert_eq!(
+            aggregate(&[CheckStatus::Disabled, CheckStatus::Degraded]),
+            (StatusCode::OK, CheckStatus::Degraded)
+        );
+    }
+
+    #[test]
+    fn report_has_the_worst_status() {
+        assert_eq!(
+            aggregate(&[CheckStatus::Ok, CheckStatus::Degraded, CheckStatus::Ok]),
+            (StatusCode::OK, CheckStatus::Degraded)
+        );
+        assert_eq!(
+            aggregate(&[CheckStatus::Degraded, CheckStatus::Down, CheckStatus::Ok]),
+            (StatusCode::SERVICE_UNAVAILABLE, CheckStatus::Down)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_and_slow_checks_are_down() {
+        let failed = run_check("failed", async {
+            Err(ApiError::GenericErr("unreachable".to_string()))
+        })
+        .await;
+        assert_eq!(failed.status, CheckStatus::Down);
+        assert_eq!(
+            failed.details.as_deref(),
+            Some("Generic Error : unreachable")
+        );
+
+        tokio::time::pause();
+        let slow = run_check("slow", async {
+            tokio::time::sleep(CHECK_TIMEOUT * 2).await;
+            Ok((CheckStatus::Ok, "reachable".to_string()))
+        })
+        .await;
+        assert_eq!(slow.status, CheckStatus::Down);
+        assert_eq!(slow.details.as_deref(), Some("timed out"));
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{RawPathParams, Request, State};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use entities::idempotency_key;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, SqlErr,
+};
+use sha2::{Digest, Sha256};
+
+use crate::error::ApiError;
+use crate::AppState;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Set on responses replayed from a previous request
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const STATUS_IN_PROGRESS: &str = "in_progress";
+const STATUS_COMPLETED: &str = "completed";
+/// Responses of the mutating routes are small, bigger ones are not stored
+const MAX_STORED_BODY_SIZE: usize = 64 * 1024;
+/// A request still in progress after this is considered lost, e.g. the replica crashed,
+/// and a retry takes its key. It's longer than a grant waiting for its tx inclusion
+const IN_PROGRESS_TTL_SECS: i64 = 300;
+const PURGE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Key of the request, from the `Idempotency-Key` header or implicitly from the address and tx
+/// hash of the path. Keys are scoped to the method and path
+fn request_key(request: &Request, params: &RawPathParams) -> Option<String> {
+    let explicit_key = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|key| key.to_str().ok());
+    let has_param = |name: &str| params.iter().any(|(key, _)| key == name);
+    if explicit_key.is_none() && !(has_param("address") && has_param("txhash")) {
+        return None;
+    }
+
+    let scope = format!(
+        "{} {} {}",
+        request.method(),
+        request.uri().path(),
+        explicit_key.unwrap_or_default()
+    );
+    Some(hex::encode(Sha256::digest(scope.as_bytes())))
+}
+
+/// Reserves the key, returns the previous request with that key if it's still valid
+async fn reserve_key(
+    db: &DatabaseConnection,
+    key: &str,
+) -> Result<Option<idempotency_key::Model>, ApiError> {
+    let now = Utc::now();
+    let reservation = idempotency_key::ActiveModel {
+        key: Set(key.to_string()),
+        status: Set(STATUS_IN_PROGRESS.to_string()),
+        created_at: Set(now),
+        expires_at: Set(now + chrono::Duration::seconds(IN_PROGRESS_TTL_SECS)),
+        ..Default::default()
+    };
+
+    match reservation.clone().insert(db).await {
+        Ok(_) => return Ok(None),
+        Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let existing = idempotency_key::Entity::find()
+        .filter(idempotency_key::Column::Key.eq(key))
+        .one(db)
+        .await?;
+    match existing {
+        Some(existing) if existing.expires_at > now => Ok(Some(existing)),
+        // The previous request is out of the window, we take its place.
+        // Only one of the concurrent retries takes it, the others are duplicates
+        Some(_) => {
+            let taken = idempotency_key::Entity::update_many()
+                .col_expr(
+                    idempotency_key::Column::Status,
+                    Expr::value(STATUS_IN_PROGRESS),
+                )
+                .col_expr(
+                    idempotency_key::Column::ResponseStatus,
+                    Expr::value(Option::<i32>::None),
+                )
+                .col_expr(
+                    idempotency_key::Column::ResponseBody,
+                    Expr::value(Option::<String>::None),
+                )
+                .col_expr(idempotency_key::Column::CreatedAt, Expr::value(now))
+                .col_expr(
+                    idempotency_key::Column::ExpiresAt,
+                    Expr::value(now + chrono::Duration::seconds(IN_PROGRESS_TTL_SECS)),
+                )
+                .filter(idempotency_key::Column::Key.eq(key))
+                .filter(idempotency_key::Column::ExpiresAt.lte(now))
+                .exec(db)
+                .await?;
+            match taken.rows_affected {
+                0 => Err(duplicate_request()),
+                _ => Ok(None),
+            }
+        }
+        // The previous request released its key in the meantime
+        None => match reservation.insert(db).await {
+            Ok(_) => Ok(None),
+            Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                Err(duplicate_request())
+            }
+            Err(e) => Err(e.into()),
+        },
+    }
+}
+
+fn duplicate_request() -> ApiError {
+    ApiError::Conflict("a request with the same idempotency key is in progress".to_string())
+}
+
+async fn release_key(db: &DatabaseConnection, key: &str) -> Result<(), ApiError> {
+    idempotency_key::Entity::delete_many()
+        .filter(idempotency_key::Column::Key.eq(key))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Releases the key if the request doesn't complete, e.g. when the client goes away
+struct Reservation {
+    db: DatabaseConnection,
+    key: Option<String>,
+}
+
+impl Reservation {
+    /// The key is kept, with the stored response
+    fn keep(mut self) {
+        self.key = None;
+    }
+
+    /// Releases the key before answering, so that an immediate retry is not seen as a duplicate
+    async fn release(mut self) -> Result<(), ApiError> {
+        match self.key.take() {
+            Some(key) => release_key(&self.db, &key).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let db = self.db.clone();
+            tokio::spawn(async move {
+                if let Err(e) = release_key(&db, &key).await {
+                    tracing::error!("Could not release the idempotency key {}: {}", key, e);
+                }
+            });
+        }
+    }
+}
+
+/// Deletes the keys out of their window, and the reservations of lost requests
+pub async fn purge_expired_keys(db: &DatabaseConnection) -> Result<u64, ApiError> {
+    let result = idempotency_key::Entity::delete_many()
+        .filter(idempotency_key::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// Purges the expired keys periodically until shutdown
+pub fn spawn_idempotency_purge(state: Arc<AppState>) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => return,
+            }
+            match purge_expired_keys(&state.db).await {
+                Ok(purged) => tracing::debug!("Purged {} expired idempotency keys", purged),
+                Err(e) => tracing::error!("Could not purge the idempotency keys: {}", e),
+            }
+        }
+    });
+}
+
+/// Middleware running a request at most once per key within the idempotency window
+///
+/// Replays get the stored response and concurrent duplicates get a 409. Only successful
+/// responses are stored, the keys of the other ones are released so that the request can be retried
+pub async fn idempotency(
+    State(state): State<Arc<AppState>>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(key) = request_key(&request, &params) else {
+        return Ok(next.run(request).await);
+    };
+
+    if let Some(previous) = reserve_key(&state.db, &key).await? {
+        if previous.status != STATUS_COMPLETED {
+            return Err(duplicate_request());
+        }
+        let status = previous
+            .response_status
+            .and_then(|status| StatusCode::from_u16(status as u16).ok())
+            .unwrap_or(StatusCode::OK);
+        let mut response = (status, previous.response_body.unwrap_or_default()).into_response();
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+        return Ok(response);
+    }
+
+    let reservation = Reservation {
+        db: state.db.clone(),
+        key: Some(key.clone()),
+    };
+    let response = next.run(request).await;
+    // Rejections can succeed later, e.g. once granting is resumed
+    if !response.status().is_success() {
+        reservation.release().await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, MAX_STORED_BODY_SIZE)
+        .await
+        .map_err(|e| ApiError::GenericErr(format!("Could not read the response: {}", e)))?;
+
+    let now = Utc::now();
+    idempotency_key::Entity::update_many()
+        .col_expr(
+            idempotency_key::Column::Status,
+            Expr::value(STATUS_COMPLETED),
+        )
+        .col_expr(
+            idempotency_key::Column::ResponseStatus,
+            Expr::value(parts.status.as_u16() as i32),
+        )
+        .col_expr(
+            idempotency_key::Column::ResponseBody,
+            Expr::value(String::from_utf8_lossy(&body).to_string()),
+        )
+        .col_expr(
+            idempotency_key::Column::ExpiresAt,
+            Expr::value(now + state.config.idempotency_window()),
+        )
+        .filter(idempotency_key::Column::Key.eq(key))
+        .exec(&state.db)
+        .await?;
+    reservation.keep();
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use sea_orm::{
+    ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, Statement,
+    TransactionTrait,
+};
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+use crate::error::ApiError;
+
+/// Maximum time an indexing job holds the lease of an address.
+/// It's also the maximum time a second caller waits for the running job
+const INDEX_LEASE: Duration = Duration::from_secs(300);
+/// The Redis lease is extended at this interval while the job runs, so that it can't expire under it
+const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(100);
+const LEASE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Deletes the lease only if it's still owned by the caller
+const RELEASE_LEASE_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("del", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// Extends the lease only if it's still owned by the caller
+const RENEW_LEASE_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("pexpire", KEYS[1], ARGV[2])
+else
+    return 0
+end
+"#;
+
+type IndexJob = Shared<BoxFuture<'static, Result<(), String>>>;
+
+/// Indexing jobs currently running in this process, by address
+pub struct IndexLocks {
+    running: Arc<Mutex<HashMap<String, IndexJob>>>,
+    /// Jobs are awaited on shutdown
+    tasks: TaskTracker,
+}
+
+impl IndexLocks {
+    pub fn new(tasks: TaskTracker) -> Self {
+        Self {
+            running: Default::default(),
+            tasks,
+        }
+    }
+
+    /// Runs the job, unless a job is already running for this address.
+    /// In that case, the result of the running job is returned once it's done.
+    /// The job runs in its own task, it's not cancelled if the caller goes away
+    pub async fn run_once<F>(&self, address: &str, job: F) -> Result<(), ApiError>
+    where
+        F: Future<Output = Result<(), ApiError>> + Send + 'static,
+    {
+        let running_job = {
+            let mut running = self.running.lock().unwrap();
+            match running.get(address) {
+                Some(running_job) => running_job.clone(),
+                None => {
+                    let running_jobs = self.running.clone();
+                    let key = address.to_string();
+                    // The job is traced under the request that started it
+                    let handle = self.tasks.spawn(
+                        async move {
+                            let result = job.await.map_err(|e| e.to_string());
+                            running_jobs.lock().unwrap().remove(&key);
+                            result
+                        }
+                        .in_current_span(),
+                    );
+                    let running_job = handle
+                        .map(|result| result.unwrap_or_else(|e| Err(e.to_string())))
+                        .boxed()
+                        .shared();
+                    running.insert(address.to_string(), running_job.clone());
+                    running_job
+                }
+            }
+        };
+
+        running_job.await.map_err(ApiError::GenericErr)
+    }
+}
+
+/// Runs the job while holding the lease of the address across all replicas.
+/// The lease lives in Redis when available, it's a MySQL named lock otherwise.
+/// If another replica holds the lease, this waits for it to be released and doesn't run the job
+pub async fn with_index_lease<F>(
+    redis: Option<ConnectionManager>,
+    db: &DatabaseConnection,
+    address: &str,
+    job: F,
+) -> Result<(), ApiError>
+where
+    F: Future<Output = Result<(), ApiError>>,
+{
+    match redis {
+        Some(connection) => {
+            with_redis_lease(connection, &format!("index-lock:{}", address), job).await
+        }
+        None => with_mysql_lock(db, &format!("index:{}", address), job).await,
+    }
+}
+
+/// Same as [`with_index_lease`], for a job that should run on a single replica at a time
+pub async fn with_job_lease<F>(
+    redis: Option<ConnectionManager>,
+    db: &DatabaseConnection,
+    job_name: &str,
+    job: F,
+) -> Result<(), ApiError>
+where
+    F: Future<Output = Result<(), ApiError>>,
+{
+    match redis {
+        Some(connection) => {
+            with_redis_lease(connection, &format!("job-lock:{}", job_name), job).await
+        }
+        None => with_mysql_lock(db, &format!("job:{}", job_name), job).await,
+    }
+}
+
+async fn with_redis_lease<F>(
+    mut connection: ConnectionManager,
+    key: &str,
+    job: F,
+) -> Result<(), ApiError>
+where
+    F: Future<Output = Result<(), ApiError>>,
+{
+    let key = key.to_string();
+    let token = lease_token();
+
+    let acquired = redis::cmd("SET")
+        .arg(&key)
+        .arg(&token)
+        .arg("NX")
+        .arg("PX")
+        .arg(INDEX_LEASE.as_millis() as u64)
+        .query_async::<_, Option<String>>(&mut connection)
+        .await?
+        .is_some();
+
+    if acquired {
+        tokio::pin!(job);
+        let result = tokio::select! {
+            result = &mut job => result,
+            // The renewal only stops when the lease was lost, the job still completes
+            _ = renew_lease(connection.clone(), &key, &token) => job.await,
+        };
+        if let Err(e) = redis::Script::new(RELEASE_LEASE_SCRIPT)
+            .key(&key)
+            .arg(&token)
+            .invoke_async::<_, i32>(&mut connection)
+            .await
+        {
+            tracing::error!("Could not release the lease {}: {}", key, e);
+        }
+        return result;
+    }
+
+    // Another replica is running the job, its results will be in the database
+    tracing::info!("Waiting for another replica to release {}", key);
+    let deadline = Instant::now() + INDEX_LEASE;
+    while Instant::now() < deadline {
+        tokio::time::sleep(LEASE_POLL_INTERVAL).await;
+        if !connection.exists::<_, bool>(&key).await? {
+            return Ok(());
+        }
+    }
+
+    Err(ApiError::GenericErr(format!(
+        "Timed out waiting for the lease {}",
+        key
+    )))
+}
+
+/// Extends the lease periodically, returns once it's no longer owned by the caller
+async fn renew_lease(mut connection: ConnectionManager, key: &str, token: &str) {
+    let mut interval = tokio::time::interval(LEASE_RENEW_INTERVAL);
+    // The first tick completes immediately, the lease was just taken
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match redis::Script::new(RENEW_LEASE_SCRIPT)
+            .key(key)
+            .arg(token)
+            .arg(INDEX_LEASE.as_millis() as u64)
+            .invoke_async::<_, i32>(&mut connection)
+            .await
+        {
+            Ok(1) => {}
+            Ok(_) => {
+                tracing::error!("Lost the index lease {} while indexing", key);
+                return;
+            }
+            // The lease is still valid until it expires, the next renewal can succeed
+            Err(e) => tracing::warn!("Could not renew the index lease {}: {}", key, e),
+        }
+    }
+}
+
+async fn with_mysql_lock<F>(db: &DatabaseConnection, name: &str, job: F) -> Result<(), ApiError>
+where
+    F: Future<Output = Result<(), ApiError>>,
+{
+    // MySQL named locks belong to a connection, the transaction pins one from the pool
+    let txn = db.begin().await?;
+
+    if get_lock(&txn, name).await? {
+        let result = job.await;
+        release_lock(&txn, name).await?;
+        txn.commit().await?;
+        return result;
+    }
+
+    // The connection goes back to the pool while waiting, the lock is polled instead
+    txn.commit().await?;
+
+    // Another replica is running the job, its results will be in the database
+    tracing::info!("Waiting for another replica to release {}", name);
+    let deadline = Instant::now() + INDEX_LEASE;
+    while Instant::now() < deadline {
+        tokio::time::sleep(LEASE_POLL_INTERVAL).await;
+        if is_free_lock(db, name).await? {
+            return Ok(());
+        }
+    }
+
+    Err(ApiError::GenericErr(format!(
+        "Timed out waiting for the lock {}",
+        name
+    )))
+}
+
+/// Takes the lock if it's free, without waiting for it
+async fn get_lock(txn: &DatabaseTransaction, name: &str) -> Result<bool, ApiError> {
+    let row = txn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::MySql,
+            "SELECT GET_LOCK(?, 0) AS locked",
+            [name.into()],
+        ))
+        .await?;
+    let locked = row
+        .map(|row| row.try_get::<Option<i64>>("", "locked"))
+        .transpose()?
+        .flatten();
+    Ok(locked == Some(1))
+}
+
+async fn is_free_lock(db: &DatabaseConnection, name: &str) -> Result<bool, ApiError> {
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::MySql,
+            "SELECT IS_FREE_LOCK(?) AS free",
+            [name.into()],
+        ))
+        .await?;
+    let free = row
+        .map(|row| row.try_get::<Option<i64>>("", "free"))
+        .transpose()?
+        .flatten();
+    Ok(free == Some(1))
+}
+
+async fn release_lock(txn: &DatabaseTransaction, name: &str) -> Result<(), ApiError> {
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::MySql,
+        "SELECT RELEASE_LOCK(?)",
+        [name.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Identifies the lease holder, so that only the holder can release it
+fn lease_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{}-{}", std::process::id(), nanos)
+}
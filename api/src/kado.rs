@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use cosmwasm_std::Uint128;
+use entities::{events_tx, kado_order};
+use hmac::{Hmac, Mac};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QueryTrait, Set,
+};
+use sha2::Sha256;
+
+use crate::error::ApiError;
+use crate::types::kado::{KadoOrderWebhook, MatchStatus, ReconciliationReport};
+use crate::AppState;
+
+/// Hex encoded HMAC-SHA256 of the raw body, keyed with the shared Kado secret
+pub const KADO_SIGNATURE_HEADER: &str = "x-kado-signature";
+/// Kado sends USDC amounts, deposits are indexed in micro units
+const USDC_DECIMALS: u32 = 6;
+/// Deposits are searched between slightly before and a few hours after the order creation
+const MATCH_WINDOW_BEFORE_MINUTES: i64 = 10;
+const MATCH_WINDOW_AFTER_HOURS: i64 = 6;
+
+fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> Result<(), ApiError> {
+    let signature = headers
+        .get(KADO_SIGNATURE_HEADER)
+        .and_then(|s| s.to_str().ok())
+        .and_then(|s| hex::decode(s).ok())
+        .ok_or_else(|| ApiError::Unauthorized("missing Kado signature".to_string()))?;
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body);
+    mac.verify_slice(&signature)
+        .map_err(|_| ApiError::Unauthorized("invalid Kado signature".to_string()))
+}
+
+/// Receives the Kado order webhooks, saves the order and tries to match it with a deposit
+pub async fn kado_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), ApiError> {
+    let secret = state
+        .config
+        .kado
+        .webhook_secret
+        .as_ref()
+        .ok_or_else(|| ApiError::Unauthorized("Kado webhooks are not enabled".to_string()))?;
+    verify_signature(secret, &headers, &body)?;
+
+    let webhook: KadoOrderWebhook = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::InvalidPayload(format!("invalid Kado order: {}", e)))?;
+    let payload: serde_json::Value = serde_json::from_slice(&body)?;
+
+    let order = save_order(&state.db, webhook, payload).await?;
+    if order.match_status != MatchStatus::Matched.as_str() {
+        reconcile_order(&state.db, order).await?;
+    }
+
+    Ok(())
+}
+
+async fn save_order(
+    db: &DatabaseConnection,
+    webhook: KadoOrderWebhook,
+    payload: serde_json::Value,
+) -> Result<kado_order::Model, ApiError> {
+    let existing_order = kado_order::Entity::find()
+        .filter(kado_order::Column::OrderId.eq(webhook.order_id.clone()))
+        .one(db)
+        .await?;
+
+    // Kado notifies every status change of the order, we only keep the latest one
+    if let Some(existing_order) = existing_order {
+        let mut existing_order: kado_order::ActiveModel = existing_order.into();
+        existing_order.status = Set(webhook.status);
+        existing_order.payload = Set(payload);
+        return Ok(existing_order.update(db).await?);
+    }
+
+    let amount = Uint128::new(10u128.pow(USDC_DECIMALS)) * webhook.receive_amount;
+    let order = kado_order::ActiveModel {
+        order_id: Set(webhook.order_id),
+        receiver: Set(webhook.wallet_address),
+        amount: Set(amount.to_string()),
+        status: Set(webhook.status),
+        ordered_at: Set(webhook.created_at),
+        payload: Set(payload),
+        match_status: Set(MatchStatus::Unmatched.as_str().to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    Ok(order.insert(db).await?)
+}
+
+/// Looks for the deposit corresponding to the order, by receiver, amount and time window
+pub async fn reconcile_order(
+    db: &DatabaseConnection,
+    order: kado_order::Model,
+) -> Result<MatchStatus, ApiError> {
+    // Deposits already matched with another order can't be matched again
+    let claimed_txs = kado_order::Entity::find()
+        .filter(kado_order::Column::Receiver.eq(order.receiver.clone()))
+        .filter(kado_order::Column::MatchStatus.eq(MatchStatus::Matched.as_str()))
+        .filter(kado_order::Column::Id.ne(order.id))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|o| o.matched_tx_id)
+        .collect::<Vec<_>>();
+
+    let window_start = order.ordered_at - Duration::minutes(MATCH_WINDOW_BEFORE_MINUTES);
+    let window_end = order.ordered_at + Duration::hours(MATCH_WINDOW_AFTER_HOURS);
+
+    let candidates = events_tx::Entity::find()
+        .filter(events_tx::Column::Address.eq(order.receiver.clone()))
+        .filter(events_tx::Column::KadoAmount.is_not_null())
+        .apply_if(
+            (!claimed_txs.is_empty()).then_some(claimed_txs),
+            |q, claimed| q.filter(events_tx::Column::Id.is_not_in(claimed)),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|tx| {
+            let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
+                .ok()?
+                .with_timezone(&Utc);
+            (timestamp >= window_start && timestamp <= window_end).then_some((timestamp, tx))
+        })
+        .collect::<Vec<_>>();
+
+    let exact_match = candidates
+        .iter()
+        .find(|(_, tx)| tx.kado_amount.as_ref() == Some(&order.amount));
+    let closest = candidates
+        .iter()
+        .min_by_key(|(timestamp, _)| (*timestamp - order.ordered_at).num_seconds().abs());
+
+    let (match_status, matched_tx_id) = match (exact_match, closest) {
+        (Some((_, tx)), _) => (MatchStatus::Matched, Some(tx.id)),
+        (None, Some((_, tx))) => (MatchStatus::Mismatched, Some(tx.id)),
+        (None, None) => (MatchStatus::Unmatched, None),
+    };
+
+    tracing::info!(
+        "Kado order {} is {:?} (tx {:?})",
+        order.order_id,
+        match_status,
+        matched_tx_id
+    );
+
+    let mut order: kado_order::ActiveModel = order.into();
+    order.match_status = Set(match_status.as_str().to_string());
+    order.matched_tx_id = Set(matched_tx_id);
+    order.update(db).await?;
+
+    Ok(match_status)
+}
+
+/// Tries to match again all the orders that were not matched yet
+pub async fn reconcile_pending_orders(
+    db: &DatabaseConnection,
+    receiver: Option<String>,
+) -> Result<(), ApiError> {
+    let pending_orders = kado_order::Entity::find()
+        .filter(kado_order::Column::MatchStatus.ne(MatchStatus::Matched.as_str()))
+        .apply_if(receiver, |q, receiver| {
+            q.filter(kado_order::Column::Receiver.eq(receiver))
+        })
+        .all(db)
+        .await?;
+
+    for order in pending_orders {
+        reconcile_order(db, order).await?;
+    }
+
+    Ok(())
+}
+
+/// Lists the orders that could not be matched with a deposit, or with the wrong amount
+pub async fn reconciliation_report(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReconciliationReport>, ApiError> {
+    Ok(Json(unreconciled_orders(&state.db).await?))
+}
+
+/// Matches the pending orders again and reports the ones left
+pub async fn reconcile(db: &DatabaseConnection) -> Result<ReconciliationReport, ApiError> {
+    reconcile_pending_orders(db, None).await?;
+    unreconciled_orders(db).await
+}
+
+/// Orders left unmatched or mismatched by the last reconciliation, nothing is written
+pub async fn unreconciled_orders(
+    db: &DatabaseConnection,
+) -> Result<ReconciliationReport, ApiError> {
+    let orders_with_status = |match_status: MatchStatus| {
+        kado_order::Entity::find()
+            .filter(kado_order::Column::MatchStatus.eq(match_status.as_str()))
+            .order_by_desc(kado_order::Column::OrderedAt)
+            .all(db)
+    };
+
+    Ok(ReconciliationReport {
+        unmatched: orders_with_status(MatchStatus::Unmatched).await?,
+        mismatched: orders_with_status(MatchStatus::Mismatched).await?,
+    })
+}
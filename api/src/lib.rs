@@ -0,0 +1,448 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::grpc::GrpcChannel;
+use crate::{
+    admin::{admin_router, parse_admin_keys},
+    backfill::spawn_backfill_resumer,
+    cache::{fee_grant_key, Cache, CacheTtls},
+    config::Config,
+    cors::{mutating_cors, public_cors, require_mutating_origin},
+    db_helpers::{
+        has_had_fee_grant, service_flag, set_fee_grant_requested, tx_was_deposited,
+        unknown_deposit, FLAG_GRANTING_PAUSED,
+    },
+    events::{address_events, spawn_event_relay, AddressEvent, EventBus},
+    fee_grants::cached_simulate_grant,
+    health::{healthz, readyz, status},
+    idempotency::{idempotency, spawn_idempotency_purge},
+    index_lock::IndexLocks,
+    kado::kado_webhook,
+    metrics::{metrics_handler, spawn_metrics_collector, track_requests, Metrics},
+    openapi::openapi_routes,
+    rate_limit::{rate_limit, BucketConfig, RateLimitConfig, RateLimiter},
+    risk::{
+        ExistingBalanceCheck, MinDepositCheck, RiskCheck, RiskContext, RiskEngine,
+        SharedSenderCheck,
+    },
+    shutdown::spawn_grant_resumer,
+    signer::{spawn_signer, Signer},
+    summary::get_address_summary,
+    sweeper::spawn_sweeper,
+    telemetry::{request_span, REQUEST_ID_HEADER},
+    tx_indexer::run_indexer,
+    types::{admin::AdminKey, grants::GrantSimulationResult},
+    versioning::{deprecated_alias, legacy_get_txs, legacy_simulate_grant_fee_to, API_V1},
+    webhooks::spawn_webhook_dispatcher,
+};
+use axum::{
+    extract::{Path, State},
+    middleware,
+    routing::{get, post},
+    Json, Router,
+};
+use cw_orch::daemon::{
+    networks::{PHOENIX_1, PISCO_1},
+    DaemonAsyncBuilder,
+};
+use entities::events_tx;
+use error::ApiError;
+use fee_grants::grant;
+use sea_orm::{ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tx_indexer::{get_tx_count, get_tx_total, get_txs};
+
+/// Shared by the handlers, the background tasks and the CLI
+pub struct AppState {
+    pub sender: String,
+    pub signer: Signer,
+    pub channel: GrpcChannel,
+    pub db: DatabaseConnection,
+    pub events: EventBus,
+    pub cache: Cache,
+    pub index_locks: IndexLocks,
+    /// Backfill segments scanned at the same time, shared by the jobs
+    pub backfill_slots: Semaphore,
+    pub rate_limiter: RateLimiter,
+    pub risk_engine: RiskEngine,
+    pub admin_keys: Vec<AdminKey>,
+    /// Cancelled on shutdown, long running work stops at the next safe point
+    pub shutdown: CancellationToken,
+    /// Background work that is awaited on shutdown
+    pub tasks: TaskTracker,
+    pub metrics: Metrics,
+    pub config: Config,
+}
+
+pub mod admin;
+pub mod backfill;
+pub mod cache;
+pub mod config;
+pub mod cors;
+pub mod db_helpers;
+pub mod error;
+pub mod events;
+pub mod fee_grants;
+pub mod gas;
+pub mod grpc;
+pub mod health;
+pub mod idempotency;
+pub mod index_lock;
+pub mod kado;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
+pub mod risk;
+pub mod shutdown;
+pub mod signer;
+pub mod summary;
+pub mod sweeper;
+pub mod telemetry;
+pub mod tx_indexer;
+pub mod types;
+pub mod versioning;
+pub mod webhooks;
+
+/// Connects to the chain, the database and the cache, and starts the signer
+pub async fn init_state(mut config: Config) -> Result<Arc<AppState>, ApiError> {
+    // Kept in sync with KNOWN_CHAIN_IDS
+    let mut chain = match config.chain.chain_id.as_str() {
+        "phoenix-1" => PHOENIX_1,
+        "pisco-1" => PISCO_1,
+        other => {
+            return Err(ApiError::InvalidConfig(format!(
+                "unknown chain.chain_id {}",
+                other
+            )))
+        }
+    };
+    // cw-orch expects static urls, they live as long as the process anyway
+    chain.grpc_urls = Vec::leak(
+        config
+            .chain
+            .grpc_urls
+            .iter()
+            .map(|url| &*String::leak(url.clone()))
+            .collect(),
+    );
+    config.chain.gas_price.get_or_insert(chain.gas_price);
+    let daemon = DaemonAsyncBuilder::default()
+        .chain(chain.clone())
+        .mnemonic(config.granter.mnemonic()?)
+        .build()
+        .await?;
+
+    let sender = daemon.sender().to_string();
+    let db = Database::connect(&config.database.url).await?;
+    let cache = Cache::new(
+        config.redis.url.clone(),
+        CacheTtls {
+            tx_total: Duration::from_secs(config.cache.tx_total_ttl_secs),
+            fee_grant: Duration::from_secs(config.cache.fee_grant_ttl_secs),
+        },
+    )
+    .await;
+    let rate_limiter = RateLimiter::new(
+        RateLimitConfig {
+            per_ip: BucketConfig {
+                capacity: config.rate_limit.ip_burst,
+                per_minute: config.rate_limit.ip_per_minute,
+            },
+            per_address: BucketConfig {
+                capacity: config.rate_limit.address_burst,
+                per_minute: config.rate_limit.address_per_minute,
+            },
+            trusted_proxies: config.server.trusted_proxies.clone(),
+        },
+        cache.redis(),
+    );
+
+    let risk_checks: Vec<Box<dyn RiskCheck>> = if config.features.risk_checks {
+        vec![
+            Box::new(MinDepositCheck {
+                min_amount: config.risk.min_deposit.into(),
+            }),
+            Box::new(SharedSenderCheck {
+                max_grantees_per_sender: config.risk.max_grantees_per_sender,
+            }),
+            Box::new(ExistingBalanceCheck {
+                max_balance: config.risk.max_grantee_balance.into(),
+                denom: config.fees.denom.clone(),
+            }),
+        ]
+    } else {
+        tracing::warn!("Risk checks are disabled, every grant request is accepted");
+        vec![]
+    };
+
+    // Only the signer uses the daemon, the queries go through the channel
+    let metrics = Metrics::new()?;
+    let (signer, signer_inbox) = Signer::new();
+    let (events, event_outbox) = EventBus::new(cache.redis().is_some());
+    let tasks = TaskTracker::new();
+    let state = Arc::new(AppState {
+        channel: GrpcChannel::new(daemon.channel(), &metrics),
+        signer,
+        sender,
+        db,
+        events,
+        cache,
+        index_locks: IndexLocks::new(tasks.clone()),
+        backfill_slots: Semaphore::new(config.backfill.concurrency),
+        rate_limiter,
+        risk_engine: RiskEngine::new(risk_checks),
+        admin_keys: parse_admin_keys(&config.admin.api_keys)?,
+        shutdown: CancellationToken::new(),
+        tasks,
+        metrics,
+        config,
+    });
+
+    spawn_signer(state.clone(), daemon, signer_inbox);
+    if let Some(outbox) = event_outbox {
+        spawn_event_relay(state.clone(), outbox);
+    }
+    Ok(state)
+}
+
+/// Starts the work the server does besides answering requests
+pub fn spawn_background_tasks(state: &Arc<AppState>) {
+    let features = &state.config.features;
+    if features.webhooks {
+        spawn_webhook_dispatcher(&state.events, state.db.clone(), state.tasks.clone());
+    }
+    spawn_grant_resumer(state.clone());
+    spawn_backfill_resumer(state.clone());
+    spawn_sweeper(state.clone());
+    spawn_idempotency_purge(state.clone());
+    if features.metrics_collector {
+        spawn_metrics_collector(state.clone());
+    }
+}
+
+/// Every route of the server, with the request tracing and metrics
+pub fn app_router(state: Arc<AppState>) -> Router {
+    // Routes that cost us gRPC queries or LUNA are rate limited, and run once per idempotency key
+    let mutating_routes = Router::new()
+        .route("/fee-grant/:address/:txhash", post(grant_fee_to))
+        .route("/index/:address", get(index_address))
+        .route("/executed/:address/:txhash", post(is_tx_executed))
+        .route_layer(middleware::from_fn_with_state(state.clone(), idempotency))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_mutating_origin,
+        ))
+        // Not a route layer, so that preflight requests are answered
+        .layer(mutating_cors(&state.config.cors));
+
+    let public_routes = Router::new()
+        .route("/fee-grant/:address", get(simulate_grant_fee_to))
+        .route("/txs/:address", get(get_txs))
+        .route("/tx-total/:address", get(get_tx_total))
+        .route("/tx-count/:address", get(get_tx_count))
+        .route("/address/:address/summary", get(get_address_summary))
+        .route("/address/:address/events", get(address_events))
+        .layer(public_cors(&state.config.cors));
+
+    // The unversioned paths are kept for the clients that don't use /v1 yet,
+    // with the responses they had before it
+    let legacy_routes = Router::new()
+        .route("/fee-grant/:address", get(legacy_simulate_grant_fee_to))
+        .route("/txs/:address", get(legacy_get_txs))
+        .route("/tx-total/:address", get(get_tx_total))
+        .route("/tx-count/:address", get(get_tx_count))
+        .layer(public_cors(&state.config.cors));
+
+    Router::new()
+        .nest(API_V1, mutating_routes.clone().merge(public_routes))
+        .merge(
+            mutating_routes
+                .merge(legacy_routes)
+                .layer(middleware::from_fn(deprecated_alias)),
+        )
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status))
+        // Called by servers only, browsers get no CORS headers
+        .route("/kado/webhook", post(kado_webhook))
+        .route("/metrics", get(metrics_handler))
+        .merge(openapi_routes())
+        .nest("/admin", admin_router(state.clone()))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_requests,
+        ))
+        // Layers run from the last one: the request id is set before the span is created
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+        .with_state(state)
+}
+
+/// Grants fees to the address so it can execute its deposit
+#[utoipa::path(
+    post,
+    path = "/v1/fee-grant/{address}/{txhash}",
+    tag = "fee-grants",
+    params(
+        ("address" = String, Path, description = "Grantee"),
+        ("txhash" = String, Path, description = "Deposit tx the fees are requested for"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response of the key")
+    ),
+    responses(
+        (status = 200, description = "Granted, or the allowance was already enough", body = String),
+        (status = 400, description = "No deposit with this hash for the address", body = String),
+        (status = 403, description = "Rejected by the risk checks", body = String),
+        (status = 409, description = "A request with the same key is running", body = String),
+        (status = 429, description = "Rate limited", body = String),
+        (status = 503, description = "Granting is paused or the granter lacks funds", body = String)
+    )
+)]
+#[axum_macros::debug_handler]
+#[tracing::instrument(skip(state))]
+async fn grant_fee_to(
+    Path((address, txhash)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<String, ApiError> {
+    if service_flag(&state.db, FLAG_GRANTING_PAUSED).await? {
+        return Err(ApiError::GrantingPaused);
+    }
+
+    // We verify the tx hash exists, the grant is only for known deposits
+    let deposit = events_tx::Entity::find()
+        .filter(events_tx::Column::Address.eq(address.clone()))
+        .filter(events_tx::Column::TxHash.eq(txhash.clone()))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| unknown_deposit(&address, &txhash))?;
+
+    tracing::info!("Fee grant requested");
+
+    // Fee grants are free LUNA, we make sure the request is legit before granting
+    let risk_context = RiskContext {
+        grantee: &address,
+        tx_hash: &txhash,
+        deposit: Some(&deposit),
+        channel: state.channel.clone(),
+        db: &state.db,
+    };
+    if let Err(e) = state.risk_engine.assess(&risk_context).await {
+        state.metrics.grants.with_label_values(&["rejected"]).inc();
+        state.events.publish(AddressEvent::FeeGrantFailed {
+            address,
+            tx_hash: txhash,
+            error: e.to_string(),
+        });
+        return Err(e);
+    }
+
+    state.events.publish(AddressEvent::FeeGrantPending {
+        address: address.clone(),
+        tx_hash: txhash.clone(),
+    });
+
+    // Saved first, so that the grant is resumed if the process stops before it's confirmed
+    set_fee_grant_requested(address.clone(), txhash.clone(), true, &state.db).await?;
+
+    // We grant if it doesn't exist
+    let grant_result = grant(&state, address.clone()).await;
+    state
+        .cache
+        .invalidate(&fee_grant_key(&state.sender, &address))
+        .await;
+    let grant_response = match grant_result {
+        Ok(grant_response) => grant_response,
+        Err(e) => {
+            set_fee_grant_requested(address.clone(), txhash.clone(), false, &state.db).await?;
+            state.metrics.grants.with_label_values(&["failed"]).inc();
+            state.events.publish(AddressEvent::FeeGrantFailed {
+                address,
+                tx_hash: txhash,
+                error: e.to_string(),
+            });
+            return Err(e);
+        }
+    };
+
+    // When the grant function is done, and doesn't error, it means the user has enough grant for depositing
+    // We can save that in the database
+    has_had_fee_grant(address.clone(), txhash.clone(), &state.db).await?;
+    state.metrics.grants.with_label_values(&["issued"]).inc();
+
+    state.events.publish(AddressEvent::FeeGrantConfirmed {
+        address,
+        tx_hash: txhash,
+    });
+
+    Ok(grant_response)
+}
+
+/// Current allowance of the address and what a grant request would do
+#[utoipa::path(
+    get,
+    path = "/v1/fee-grant/{address}",
+    tag = "fee-grants",
+    params(("address" = String, Path, description = "Grantee")),
+    responses((status = 200, body = GrantSimulationResult))
+)]
+#[axum_macros::debug_handler]
+#[tracing::instrument(skip(state))]
+async fn simulate_grant_fee_to(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<GrantSimulationResult>, ApiError> {
+    let simulation = cached_simulate_grant(&state, address).await?;
+
+    Ok(simulation.into())
+}
+
+/// Indexes the new txs of the address
+#[utoipa::path(
+    get,
+    path = "/v1/index/{address}",
+    tag = "txs",
+    params(("address" = String, Path)),
+    responses(
+        (status = 200, description = "Indexed"),
+        (status = 429, description = "Rate limited", body = String)
+    )
+)]
+#[axum_macros::debug_handler]
+#[tracing::instrument(skip(state))]
+async fn index_address(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<String>,
+) -> Result<(), ApiError> {
+    // We fetch new transactions
+    run_indexer(state, address).await
+}
+
+/// Marks the deposit as executed
+#[utoipa::path(
+    post,
+    path = "/v1/executed/{address}/{txhash}",
+    tag = "txs",
+    params(("address" = String, Path), ("txhash" = String, Path)),
+    responses(
+        (status = 200, description = "Marked, unknown txs are ignored"),
+        (status = 429, description = "Rate limited", body = String)
+    )
+)]
+#[axum_macros::debug_handler]
+#[tracing::instrument(skip(state))]
+async fn is_tx_executed(
+    Path((address, txhash)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<(), ApiError> {
+    if tx_was_deposited(address.clone(), txhash.clone(), &state.db).await? {
+        state.events.publish(AddressEvent::DepositExecuted {
+            address,
+            tx_hash: txhash,
+        });
+    }
+    Ok(())
+}
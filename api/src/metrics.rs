@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use entities::{events_tx, fee_grant};
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
+use tokio::task::JoinHandle;
+
+use crate::db_helpers::GRANT_STATUS_ACTIVE;
+use crate::error::ApiError;
+use crate::fee_grants::query_balance;
+use crate::tx_indexer::cached_tx_total;
+use crate::AppState;
+
+/// Time between two updates of the metrics that are queried rather than recorded
+const COLLECT_INTERVAL: Duration = Duration::from_secs(30);
+/// Watched addresses whose tx count is queried on each collection, the others keep their last lag
+const LAG_SAMPLE_SIZE: usize = 20;
+
+pub struct Metrics {
+    pub registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub grpc_requests: IntCounterVec,
+    pub grpc_request_duration: HistogramVec,
+    /// Gas used by the simulation of our txs, by tx kind
+    pub tx_gas_used: HistogramVec,
+    /// Gas limit set on our txs after applying the multiplier
+    pub tx_gas_limit: HistogramVec,
+    /// Fee paid for our txs, in the fee denom
+    pub tx_fee: HistogramVec,
+    pub tx_simulation_failures: IntCounterVec,
+    pub deposits_indexed: IntCounterVec,
+    /// Grant requests by outcome: issued, failed or rejected
+    pub grants: IntCounterVec,
+    pub granter_balance: Gauge,
+    /// Txs on-chain that are not indexed yet, summed over the addresses with an active grant
+    pub indexer_lag: IntGauge,
+    /// Addresses with an active grant that have txs not indexed yet
+    pub indexer_lagging_addresses: IntGauge,
+    /// Txs not indexed yet of the addresses sampled by the last collection, by address
+    pub indexer_address_lag: IntGaugeVec,
+    pub db_pool_connections: IntGaugeVec,
+}
+
+/// Registers the collector and gives it back
+fn register<C: Collector + Clone + 'static>(
+    registry: &Registry,
+    collector: C,
+) -> Result<C, ApiError> {
+    registry.register(Box::new(collector.clone()))?;
+    Ok(collector)
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, ApiError> {
+        let registry = Registry::new_custom(Some("onboarding".to_string()), None)?;
+        let latency_buckets = exponential_buckets(0.005, 2.0, 14)?;
+        let gas_buckets = exponential_buckets(50_000.0, 1.5, 12)?;
+
+        Ok(Self {
+            http_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("http_requests_total", "HTTP requests handled"),
+                    &["method", "route", "status"],
+                )?,
+            )?,
+            http_request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("http_request_duration_seconds", "HTTP request latency")
+                        .buckets(latency_buckets.clone()),
+                    &["method", "route"],
+                )?,
+            )?,
+            grpc_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "grpc_requests_total",
+                        "gRPC calls to the node, by status code",
+                    ),
+                    &["method", "status"],
+                )?,
+            )?,
+            grpc_request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("grpc_request_duration_seconds", "gRPC call latency")
+                        .buckets(latency_buckets),
+                    &["method"],
+                )?,
+            )?,
+            tx_gas_used: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("tx_gas_used", "Simulated gas used by the txs")
+                        .buckets(gas_buckets.clone()),
+                    &["kind"],
+                )?,
+            )?,
+            tx_gas_limit: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("tx_gas_limit", "Gas limit of the txs").buckets(gas_buckets),
+                    &["kind"],
+                )?,
+            )?,
+            tx_fee: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("tx_fee", "Fee paid for the txs")
+                        .buckets(exponential_buckets(1_000.0, 2.0, 12)?),
+                    &["kind"],
+                )?,
+            )?,
+            tx_simulation_failures: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("tx_simulation_failures_total", "Txs that failed simulation"),
+                    &["kind"],
+                )?,
+            )?,
+            deposits_indexed: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("deposits_indexed_total", "Deposits found by the indexer"),
+                    &["denom"],
+                )?,
+            )?,
+            grants: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("grants_total", "Fee grant requests"),
+                    &["outcome"],
+                )?,
+            )?,
+            granter_balance: register(
+                &registry,
+                Gauge::new("granter_balance", "Balance of the granter in the fee denom")?,
+            )?,
+            indexer_lag: register(
+                &registry,
+                IntGauge::new(
+                    "indexer_lag_txs",
+                    "Txs of the watched addresses not indexed yet",
+                )?,
+            )?,
+            indexer_lagging_addresses: register(
+                &registry,
+                IntGauge::new(
+                    "indexer_lagging_addresses",
+                    "Watched addresses with txs not indexed yet",
+                )?,
+            )?,
+            indexer_address_lag: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new(
+                        "indexer_address_lag_txs",
+                        "Txs of the address not indexed yet, for the last sampled addresses",
+                    ),
+                    &["address"],
+                )?,
+            )?,
+            db_pool_connections: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("db_pool_connections", "Connections of the database pool"),
+                    &["state"],
+                )?,
+            )?,
+            registry,
+        })
+    }
+}
+
+/// Prometheus text exposition of all the metrics
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> Result<String, ApiError> {
+    Ok(TextEncoder::new().encode_to_string(&state.metrics.registry.gather())?)
+}
+
+/// Middleware counting the requests and their latency, labelled with the route template
+pub async fn track_requests(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let metrics = &state.metrics;
+    metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Last known lag of the watched addresses
+#[derive(Default)]
+struct IndexerLags {
+    lags: HashMap<String, u64>,
+    /// Position of the next addresses to query in the watched list
+    next: usize,
+}
+
+/// Periodically updates the granter balance, the indexer lag and the DB pool usage
+pub fn spawn_metrics_collector(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+        let mut lags = IndexerLags::default();
+        loop {
+            interval.tick().await;
+            collect(&state, &mut lags).await;
+        }
+    })
+}
+
+/// Updates what it can, a failing query only leaves its metric as it was
+async fn collect(state: &AppState, lags: &mut IndexerLags) {
+    let metrics = &state.metrics;
+
+    let pool = state.db.get_mysql_connection_pool();
+    let idle = pool.num_idle() as i64;
+    metrics
+        .db_pool_connections
+        .with_label_values(&["idle"])
+        .set(idle);
+    metrics
+        .db_pool_connections
+        .with_label_values(&["in_use"])
+        .set(pool.size() as i64 - idle);
+
+    let denom = &state.config.fees.denom;
+    match query_balance(state.channel.clone(), state.sender.clone(), denom).await {
+        Ok(balance) => metrics.granter_balance.set(balance.u128() as f64),
+        Err(e) => tracing::warn!("Could not collect the granter balance: {}", e),
+    }
+
+    if let Err(e) = collect_indexer_lag(state, lags).await {
+        tracing::warn!("Could not collect the indexer lag: {}", e);
+    }
+}
+
+/// Queries the tx count of a few watched addresses, so that a collection costs a bounded
+/// number of node queries however many addresses are watched
+async fn collect_indexer_lag(state: &AppState, lags: &mut IndexerLags) -> Result<(), ApiError> {
+    let watched_addresses = fee_grant::Entity::find()
+        .select_only()
+        .column(fee_grant::Column::Grantee)
+        .distinct()
+        .filter(fee_grant::Column::Status.eq(GRANT_STATUS_ACTIVE))
+        .order_by_asc(fee_grant::Column::Grantee)
+        .into_tuple::<String>()
+        .all(&state.db)
+        .await?;
+    // Addresses that are not watched anymore are dropped
+    let watched = watched_addresses.iter().collect::<HashSet<_>>();
+    lags.lags.retain(|address, _| watched.contains(address));
+    // Only the sampled addresses are labelled, so that the series stay bounded
+    state.metrics.indexer_address_lag.reset();
+
+    if !watched_addresses.is_empty() {
+        let start = lags.next % watched_addresses.len();
+        let sampled = watched_addresses
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(LAG_SAMPLE_SIZE.min(watched_addresses.len()));
+        for address in sampled {
+            match address_lag(state, address).await {
+                Ok(lag) => {
+                    state
+                        .metrics
+                        .indexer_address_lag
+                        .with_label_values(&[address])
+                        .set(lag as i64);
+                    lags.lags.insert(address.clone(), lag);
+                }
+                Err(e) => tracing::warn!("Could not collect the indexer lag of {}: {}", address, e),
+            }
+        }
+        lags.next = start + LAG_SAMPLE_SIZE;
+    }
+
+    let metrics = &state.metrics;
+    metrics
+        .indexer_lag
+        .set(lags.lags.values().sum::<u64>() as i64);
+    metrics
+        .indexer_lagging_addresses
+        .set(lags.lags.values().filter(|lag| **lag > 0).count() as i64);
+    Ok(())
+}
+
+async fn address_lag(state: &AppState, address: &str) -> Result<u64, ApiError> {
+    let total = cached_tx_total(state, &address.to_string()).await?;
+    let indexed = events_tx::Entity::find()
+        .filter(events_tx::Column::Address.eq(address))
+        .count(&state.db)
+        .await?;
+    Ok(total.saturating_sub(indexed))
+}
@@ -0,0 +1,72 @@
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::types::grants::{
+    BasicAllowanceGrant, CoinSchema, CosmosBasicAllowance, CosmosGrant, CosmosPeriodicAllowance,
+    GrantSimulationResult, PeriodicAllowanceGrant, PeriodicReset, QuerierGrant, RawAllowance,
+};
+use crate::types::health::{CheckStatus, DependencyCheck, StatusReport};
+use crate::types::summary::{AddressSummary, DenomDeposits, IndexerFreshness};
+use crate::types::txs::{SortOrder, TxAttributeDto, TxDto, TxEventDto, TxLogDto, TxsPage};
+
+/// Public routes of the API, the admin and webhook routes are not documented
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Onboarding API",
+        description = "Indexes the deposits to Terra and grants fees to execute them.\n\n\
+            The routes without the `/v1` prefix are deprecated aliases that keep their \
+            former responses, with a `Deprecation` header. Only the `/v1` routes are documented.",
+        version = "1.0.0"
+    ),
+    paths(
+        crate::grant_fee_to,
+        crate::simulate_grant_fee_to,
+        crate::index_address,
+        crate::is_tx_executed,
+        crate::tx_indexer::get_txs,
+        crate::tx_indexer::get_tx_count,
+        crate::tx_indexer::get_tx_total,
+        crate::summary::get_address_summary,
+        crate::events::address_events,
+        crate::health::healthz,
+        crate::health::readyz,
+        crate::health::status,
+    ),
+    components(schemas(
+        CoinSchema,
+        CosmosBasicAllowance,
+        CosmosPeriodicAllowance,
+        BasicAllowanceGrant,
+        PeriodicAllowanceGrant,
+        RawAllowance,
+        CosmosGrant,
+        QuerierGrant,
+        PeriodicReset,
+        GrantSimulationResult,
+        SortOrder,
+        TxAttributeDto,
+        TxEventDto,
+        TxLogDto,
+        TxDto,
+        TxsPage,
+        DenomDeposits,
+        IndexerFreshness,
+        AddressSummary,
+        CheckStatus,
+        DependencyCheck,
+        StatusReport,
+    )),
+    tags(
+        (name = "fee-grants", description = "Fee allowances given by the granter"),
+        (name = "txs", description = "Indexed txs and deposits"),
+        (name = "addresses", description = "Onboarding status of an address"),
+        (name = "health", description = "Probes and dependency status"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Serves the document at `/openapi.json`, with a Swagger UI at `/swagger-ui`
+pub fn openapi_routes() -> SwaggerUi {
+    SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi())
+}
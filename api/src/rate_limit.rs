@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, RawPathParams, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use redis::aio::ConnectionManager;
+
+use crate::error::ApiError;
+use crate::AppState;
+
+/// Token bucket kept in a Redis hash, refilled with the Redis clock so that all replicas agree.
+/// Returns 0 when a token was taken, the number of milliseconds until the next token otherwise
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_per_ms = tonumber(ARGV[2])
+local time = redis.call("TIME")
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local bucket = redis.call("HMGET", KEYS[1], "tokens", "updated_at")
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + (now - updated_at) * refill_per_ms)
+
+local retry_after = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    retry_after = math.ceil((1 - tokens) / refill_per_ms)
+end
+
+redis.call("HSET", KEYS[1], "tokens", tokens, "updated_at", now)
+redis.call("PEXPIRE", KEYS[1], math.ceil(capacity / refill_per_ms))
+return retry_after
+"#;
+
+#[derive(Clone, Copy)]
+pub struct BucketConfig {
+    /// Number of requests that can be made in a burst
+    pub capacity: u32,
+    /// Number of requests regained per minute
+    pub per_minute: u32,
+}
+
+impl BucketConfig {
+    fn refill_per_ms(&self) -> f64 {
+        self.per_minute as f64 / 60_000.0
+    }
+}
+
+pub struct RateLimitConfig {
+    pub per_ip: BucketConfig,
+    pub per_address: BucketConfig,
+    /// Proxies allowed to set the client IP with `X-Forwarded-For`
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// How often the local buckets that are full again are dropped
+const LOCAL_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Token bucket kept in memory when Redis is not available
+struct LocalBucket {
+    tokens: f64,
+    updated_at: Instant,
+    config: BucketConfig,
+}
+
+impl LocalBucket {
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed_ms = now.duration_since(self.updated_at).as_millis() as f64;
+        (self.tokens + elapsed_ms * self.config.refill_per_ms()).min(self.config.capacity as f64)
+    }
+}
+
+struct LocalBuckets {
+    buckets: HashMap<String, LocalBucket>,
+    evicted_at: Instant,
+}
+
+impl LocalBuckets {
+    /// A full bucket is the same as a missing one, dropping them keeps the map bounded
+    /// by the clients of the last minutes
+    fn evict_full(&mut self, now: Instant) {
+        if now.duration_since(self.evicted_at) < LOCAL_EVICTION_INTERVAL {
+            return;
+        }
+        self.buckets
+            .retain(|_, bucket| bucket.tokens_at(now) < bucket.config.capacity as f64);
+        self.evicted_at = now;
+    }
+}
+
+/// Token bucket rate limiter, shared across replicas through Redis when available
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    redis: Option<ConnectionManager>,
+    local_buckets: Mutex<LocalBuckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, redis: Option<ConnectionManager>) -> Self {
+        Self {
+            config,
+            redis,
+            local_buckets: Mutex::new(LocalBuckets {
+                buckets: HashMap::new(),
+                evicted_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the bucket. Returns how long to wait if the bucket is empty
+    async fn take(&self, key: &str, bucket: BucketConfig) -> Option<Duration> {
+        if let Some(mut connection) = self.redis.clone() {
+            match redis::Script::new(TOKEN_BUCKET_SCRIPT)
+                .key(key)
+                .arg(bucket.capacity)
+                .arg(bucket.refill_per_ms())
+                .invoke_async::<_, u64>(&mut connection)
+                .await
+            {
+                Ok(0) => return None,
+                Ok(retry_after_ms) => return Some(Duration::from_millis(retry_after_ms)),
+                Err(e) => tracing::warn!("Redis rate limit failed, using local buckets: {}", e),
+            }
+        }
+
+        let mut local_buckets = self.local_buckets.lock().unwrap();
+        let now = Instant::now();
+        local_buckets.evict_full(now);
+        let local_bucket = local_buckets
+            .buckets
+            .entry(key.to_string())
+            .or_insert(LocalBucket {
+                tokens: bucket.capacity as f64,
+                updated_at: now,
+                config: bucket,
+            });
+        local_bucket.tokens = local_bucket.tokens_at(now);
+        local_bucket.updated_at = now;
+
+        if local_bucket.tokens >= 1.0 {
+            local_bucket.tokens -= 1.0;
+            None
+        } else {
+            let retry_after_ms = ((1.0 - local_bucket.tokens) / bucket.refill_per_ms()).ceil();
+            Some(Duration::from_millis(retry_after_ms as u64))
+        }
+    }
+
+    /// Client IP, read from `X-Forwarded-For` when the request comes from a trusted proxy
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.config.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+        let forwarded_ips = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        // Each proxy appends the address it received the request from,
+        // the client is the last address that was not added by a trusted proxy
+        forwarded_ips
+            .into_iter()
+            .rev()
+            .find(|ip| !self.config.trusted_proxies.contains(ip))
+            .unwrap_or(peer)
+    }
+}
+
+/// Middleware limiting the requests per client IP and per target address
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let limiter = &state.rate_limiter;
+
+    let ip = limiter.client_ip(peer.ip(), request.headers());
+    if let Some(retry_after) = limiter
+        .take(&format!("rate-limit:ip:{}", ip), limiter.config.per_ip)
+        .await
+    {
+        tracing::info!("Rate limited {} on {}", ip, request.uri());
+        return Err(ApiError::RateLimited(retry_after));
+    }
+
+    if let Some((_, address)) = params.iter().find(|(key, _)| *key == "address") {
+        if let Some(retry_after) = limiter
+            .take(
+                &format!("rate-limit:address:{}", address),
+                limiter.config.per_address,
+            )
+            .await
+        {
+            tracing::info!("Rate limited address {} on {}", address, request.uri());
+            return Err(ApiError::RateLimited(retry_after));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET: BucketConfig = BucketConfig {
+        capacity: 2,
+        per_minute: 60,
+    };
+
+    fn limiter(trusted_proxies: Vec<IpAddr>) -> RateLimiter {
+        RateLimiter::new(
+            RateLimitConfig {
+                per_ip: BUCKET,
+                per_address: BUCKET,
+                trusted_proxies,
+            },
+            None,
+        )
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn empty_bucket_is_rate_limited() {
+        let limiter = limiter(vec![]);
+        assert_eq!(limiter.take("key", BUCKET).await, None);
+        assert_eq!(limiter.take("key", BUCKET).await, None);
+
+        let retry_after = limiter.take("key", BUCKET).await.unwrap();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(2));
+        // Buckets are per key
+        assert_eq!(limiter.take("other", BUCKET).await, None);
+    }
+
+    #[test]
+    fn bucket_refills_up_to_its_capacity() {
+        let now = Instant::now();
+        let bucket = LocalBucket {
+            tokens: 0.0,
+            updated_at: now,
+            config: BUCKET,
+        };
+        assert_eq!(bucket.tokens_at(now), 0.0);
+        assert!((bucket.tokens_at(now + Duration::from_millis(1500)) - 1.5).abs() < 1e-9);
+        assert_eq!(bucket.tokens_at(now + Duration::from_secs(60)), 2.0);
+    }
+
+    #[test]
+    fn full_buckets_are_evicted() {
+        let now = Instant::now();
+        let bucket = |tokens| LocalBucket {
+            tokens,
+            updated_at: now,
+            config: BUCKET,
+        };
+        let mut buckets = LocalBuckets {
+            buckets: HashMap::from([
+                ("full".to_string(), bucket(2.0)),
+                ("refilled".to_string(), bucket(0.0)),
+            ]),
+            evicted_at: now,
+        };
+
+        // Evictions are spaced out
+        buckets.evict_full(now + Duration::from_secs(1));
+        assert_eq!(buckets.buckets.len(), 2);
+
+        buckets.evict_full(now + LOCAL_EVICTION_INTERVAL);
+        assert!(buckets.buckets.is_empty());
+    }
+
+    #[test]
+    fn client_ip_is_the_peer_without_trusted_proxy() {
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let headers = forwarded_for("1.2.3.4");
+        assert_eq!(limiter(vec![]).client_ip(peer, &headers), peer);
+    }
+
+    #[test]
+    fn client_ip_skips_the_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let inner_proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let limiter = limiter(vec![proxy, inner_proxy]);
+
+        // The client can prepend anything, only the entries added by our proxies are trusted
+        let headers = forwarded_for("6.6.6.6, 1.2.3.4, 10.0.0.2");
+        assert_eq!(
+            limiter.client_ip(proxy, &headers),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(limiter.client_ip(proxy, &HeaderMap::new()), proxy);
+    }
+}
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use cosmwasm_std::Uint128;
+use entities::{events_tx, risk_decision};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QuerySelect, Set,
+};
+
+use crate::error::ApiError;
+use crate::fee_grants::query_balance;
+use crate::grpc::GrpcChannel;
+
+/// What a risk check knows about a fee grant request
+pub struct RiskContext<'a> {
+    pub grantee: &'a str,
+    pub tx_hash: &'a str,
+    /// Deposit the grant is requested for, if it was indexed
+    pub deposit: Option<&'a events_tx::Model>,
+    pub channel: GrpcChannel,
+    pub db: &'a DatabaseConnection,
+}
+
+pub struct RiskVerdict {
+    pub allowed: bool,
+    pub reason: String,
+}
+
+impl RiskVerdict {
+    pub fn allow(reason: impl Into<String>) -> Self {
+        Self {
+            allowed: true,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn reject(reason: impl Into<String>) -> Self {
+        Self {
+            allowed: false,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Heuristic deciding whether an address should get a fee grant
+#[async_trait]
+pub trait RiskCheck: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn evaluate(&self, ctx: &RiskContext<'_>) -> Result<RiskVerdict, ApiError>;
+}
+
+/// Runs all the checks before a grant and records their decisions
+pub struct RiskEngine {
+    checks: Vec<Box<dyn RiskCheck>>,
+}
+
+impl RiskEngine {
+    pub fn new(checks: Vec<Box<dyn RiskCheck>>) -> Self {
+        Self { checks }
+    }
+
+    /// Errors with the rejection reasons if any check rejects the grant
+    pub async fn assess(&self, ctx: &RiskContext<'_>) -> Result<(), ApiError> {
+        let mut rejections = vec![];
+        for check in &self.checks {
+            let verdict = check.evaluate(ctx).await?;
+            tracing::info!(
+                "Risk check {} for {}: allowed={} ({})",
+                check.name(),
+                ctx.grantee,
+                verdict.allowed,
+                verdict.reason
+            );
+
+            risk_decision::ActiveModel {
+                address: Set(ctx.grantee.to_string()),
+                tx_hash: Set(ctx.tx_hash.to_string()),
+                check_name: Set(check.name().to_string()),
+                allowed: Set(verdict.allowed.into()),
+                reason: Set(verdict.reason.clone()),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(ctx.db)
+            .await?;
+
+            if !verdict.allowed {
+                rejections.push(verdict.reason);
+            }
+        }
+
+        if !rejections.is_empty() {
+            return Err(ApiError::GrantRejected(rejections.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+/// Sender of the deposit on the source chain. Older rows only have it in their logs
+fn deposit_sender(deposit: &events_tx::Model) -> Option<String> {
+    deposit.sender.clone().or_else(|| {
+        deposit
+            .tx_events
+            .0
+            .iter()
+            .flat_map(|log| &log.events)
+            .filter(|e| e.event_type == "fungible_token_packet")
+            .flat_map(|e| &e.attributes)
+            .find(|a| a.key == "sender")
+            .map(|a| a.value.clone())
+    })
+}
+
+/// Rejects grantees funded by a sender that already funded too many other grantees
+pub struct SharedSenderCheck {
+    pub max_grantees_per_sender: u64,
+}
+
+#[async_trait]
+impl RiskCheck for SharedSenderCheck {
+    fn name(&self) -> &'static str {
+        "shared_sender"
+    }
+
+    async fn evaluate(&self, ctx: &RiskContext<'_>) -> Result<RiskVerdict, ApiError> {
+        let Some(sender) = ctx.deposit.and_then(deposit_sender) else {
+            return Ok(RiskVerdict::allow("unknown deposit sender"));
+        };
+
+        let other_grantees = events_tx::Entity::find()
+            .select_only()
+            .column(events_tx::Column::Address)
+            .distinct()
+            .filter(events_tx::Column::Sender.eq(sender.clone()))
+            .filter(events_tx::Column::HasFeeGrant.eq(true))
+            .filter(events_tx::Column::Address.ne(ctx.grantee))
+            .count(ctx.db)
+            .await?;
+
+        if other_grantees >= self.max_grantees_per_sender {
+            return Ok(RiskVerdict::reject(format!(
+                "sender {} already funded {} other grantees",
+                sender, other_grantees
+            )));
+        }
+        Ok(RiskVerdict::allow(format!(
+            "sender {} funded {} other grantees",
+            sender, other_grantees
+        )))
+    }
+}
+
+/// Rejects deposits that are too small to be worth a grant
+pub struct MinDepositCheck {
+    pub min_amount: Uint128,
+}
+
+#[async_trait]
+impl RiskCheck for MinDepositCheck {
+    fn name(&self) -> &'static str {
+        "min_deposit"
+    }
+
+    async fn evaluate(&self, ctx: &RiskContext<'_>) -> Result<RiskVerdict, ApiError> {
+        let Some(amount) = ctx.deposit.and_then(|d| d.kado_amount.as_deref()) else {
+            return Ok(RiskVerdict::reject("no deposit was indexed for this tx"));
+        };
+        let amount = Uint128::from_str(amount)?;
+
+        if amount < self.min_amount {
+            return Ok(RiskVerdict::reject(format!(
+                "deposit of {} is below {}",
+                amount, self.min_amount
+            )));
+        }
+        Ok(RiskVerdict::allow(format!("deposit of {}", amount)))
+    }
+}
+
+/// Rejects grantees that can already pay their own fees
+pub struct ExistingBalanceCheck {
+    pub max_balance: Uint128,
+    /// Denom the fees are paid in
+    pub denom: String,
+}
+
+#[async_trait]
+impl RiskCheck for ExistingBalanceCheck {
+    fn name(&self) -> &'static str {
+        "existing_balance"
+    }
+
+    async fn evaluate(&self, ctx: &RiskContext<'_>) -> Result<RiskVerdict, ApiError> {
+        let balance =
+            query_balance(ctx.channel.clone(), ctx.grantee.to_string(), &self.denom).await?;
+
+        if balance >= self.max_balance {
+            return Ok(RiskVerdict::reject(format!(
+                "grantee already holds {}{}",
+                balance, self.denom
+            )));
+        }
+        Ok(RiskVerdict::allow(format!(
+            "grantee holds {}{}",
+            balance, self.denom
+        )))
+    }
+}
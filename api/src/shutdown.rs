@@ -0,0 +1,127 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::cache::fee_grant_key;
+use crate::db_helpers::{
+    claim_fee_grant_request, has_had_fee_grant, pending_fee_grant_requests, service_flag,
+    set_fee_grant_requested, FEE_GRANT_REQUEST_LEASE_SECS, FLAG_GRANTING_PAUSED,
+};
+use crate::error::ApiError;
+use crate::fee_grants::grant;
+use crate::AppState;
+
+/// Waits for Ctrl+C or SIGTERM, then cancels the token so that the server stops accepting requests
+pub async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Could not listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Could not listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+        _ = shutdown.cancelled() => {},
+    }
+    tracing::info!("Shutting down, draining the running work");
+    shutdown.cancel();
+}
+
+/// Waits for the tracked tasks (index jobs, tx inclusions, webhook deliveries) until the deadline
+pub async fn drain(tasks: &TaskTracker, deadline: Instant) {
+    tasks.close();
+    if tokio::time::timeout_at(deadline, tasks.wait())
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "{} tasks still running at the shutdown deadline, they are resumed on the next start",
+            tasks.len()
+        );
+    }
+}
+
+/// Resumes the pending fee grants at start, then every request lease, so that the requests
+/// left by a replica stopped during a rolling restart are resumed once their lease expires
+pub fn spawn_grant_resumer(state: Arc<AppState>) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(FEE_GRANT_REQUEST_LEASE_SECS as u64));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => return,
+            }
+            if let Err(e) = resume_pending_grants(state.clone()).await {
+                tracing::error!("Could not resume the pending fee grants: {}", e);
+            }
+        }
+    });
+}
+
+/// Grants the fees requested before the last stop that were never confirmed.
+/// Each request is claimed first, so that only one instance resumes it, and a failed one
+/// is dropped: the user requests it again. Allowances that landed are only recorded,
+/// [`grant`] doesn't grant twice. Nothing is resumed while granting is paused
+pub async fn resume_pending_grants(state: Arc<AppState>) -> Result<(), ApiError> {
+    let pending = pending_fee_grant_requests(&state.db).await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("Resuming {} fee grant requests", pending.len());
+
+    for deposit in pending {
+        if state.shutdown.is_cancelled() {
+            break;
+        }
+        // Checked for each request, granting can be paused while resuming
+        if service_flag(&state.db, FLAG_GRANTING_PAUSED).await? {
+            tracing::info!("Granting is paused, the pending fee grants are resumed later");
+            break;
+        }
+        if !claim_fee_grant_request(deposit.address.clone(), deposit.tx_hash.clone(), &state.db)
+            .await?
+        {
+            continue;
+        }
+        let result = grant(&state, deposit.address.clone()).await;
+        state
+            .cache
+            .invalidate(&fee_grant_key(&state.sender, &deposit.address))
+            .await;
+        match result {
+            Ok(_) => has_had_fee_grant(deposit.address, deposit.tx_hash, &state.db).await?,
+            Err(e) => {
+                tracing::error!(
+                    "Could not resume the fee grant of {} for {}: {}",
+                    deposit.address,
+                    deposit.tx_hash,
+                    e
+                );
+                set_fee_grant_requested(deposit.address, deposit.tx_hash, false, &state.db).await?;
+            }
+        }
+    }
+    Ok(())
+}
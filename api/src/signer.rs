@@ -0,0 +1,296 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use cosmos_sdk_proto::cosmos::auth::v1beta1::BaseAccount;
+use cosmos_sdk_proto::cosmos::tx::v1beta1::service_client::ServiceClient as TxServiceClient;
+use cosmos_sdk_proto::cosmos::tx::v1beta1::GetTxRequest;
+use cosmos_sdk_proto::Any;
+use cw_orch::daemon::tx_builder::TxBuilder;
+use cw_orch::daemon::{CosmTxResponse, DaemonAsync};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Instrument, Span};
+
+use crate::backfill::latest_height;
+use crate::error::ApiError;
+use crate::gas::{estimate_fee, query_account};
+use crate::grpc::GrpcChannel;
+use crate::AppState;
+
+/// Blocks after which a tx that isn't included is dropped
+const TX_TIMEOUT_BLOCKS: u64 = 10;
+/// Txs waiting to be signed, callers wait when it's full
+const SIGNER_QUEUE_SIZE: usize = 64;
+/// Code of the `ErrWrongSequence` cosmos-sdk error
+const WRONG_SEQUENCE_CODE: u32 = 32;
+const MAX_SEQUENCE_RETRIES: usize = 3;
+/// Interval between the queries of a broadcast tx, until it's included
+const TX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Queries of a broadcast tx before giving up, it times out after `TX_TIMEOUT_BLOCKS` anyway
+const TX_POLL_ATTEMPTS: usize = 60;
+
+struct SignRequest {
+    msgs: Vec<Any>,
+    kind: &'static str,
+    reply: oneshot::Sender<Result<CosmTxResponse, ApiError>>,
+    /// Span of the caller, the tx is traced under it
+    span: Span,
+}
+
+/// Handle to the actor signing and broadcasting the granter txs
+///
+/// The actor owns the daemon and tracks the account sequence itself, so a tx can be broadcast
+/// while the previous ones are still waiting for inclusion
+#[derive(Clone)]
+pub struct Signer {
+    requests: mpsc::Sender<SignRequest>,
+}
+
+pub struct SignerInbox(mpsc::Receiver<SignRequest>);
+
+impl Signer {
+    pub fn new() -> (Self, SignerInbox) {
+        let (requests, inbox) = mpsc::channel(SIGNER_QUEUE_SIZE);
+        (Self { requests }, SignerInbox(inbox))
+    }
+
+    /// Signs and broadcasts the msgs, then waits for the tx to be included
+    pub async fn commit(
+        &self,
+        msgs: Vec<Any>,
+        kind: &'static str,
+    ) -> Result<CosmTxResponse, ApiError> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send(SignRequest {
+                msgs,
+                kind,
+                reply,
+                span: Span::current(),
+            })
+            .await
+            .map_err(|_| ApiError::GenericErr("Signer stopped".to_string()))?;
+        response
+            .await
+            .map_err(|_| ApiError::GenericErr("Signer dropped the tx".to_string()))?
+    }
+}
+
+/// Runs the signer until the shutdown token is cancelled.
+/// The state holds a [`Signer`] handle, so the inbox never closes by itself: on shutdown, new
+/// txs are refused and the ones already queued are still broadcast before the signer stops
+pub fn spawn_signer(
+    state: Arc<AppState>,
+    daemon: DaemonAsync,
+    SignerInbox(mut inbox): SignerInbox,
+) -> tokio::task::JoinHandle<()> {
+    let tasks = state.tasks.clone();
+    tasks.spawn(async move {
+        // Queried lazily, and again when the chain disagrees with our sequence
+        let mut account: Option<BaseAccount> = None;
+        let shutdown = state.shutdown.clone();
+        let mut closed = false;
+
+        loop {
+            let request = tokio::select! {
+                request = inbox.recv() => request,
+                // Once closed, the inbox still returns the queued txs, then None
+                _ = shutdown.cancelled(), if !closed => {
+                    inbox.close();
+                    closed = true;
+                    continue;
+                }
+            };
+            let Some(request) = request else {
+                return;
+            };
+
+            let mut result = Err(ApiError::GenericErr("Tx not sent".to_string()));
+            for _ in 0..MAX_SEQUENCE_RETRIES {
+                result = broadcast(&state, &daemon, &mut account, &request)
+                    .instrument(request.span.clone())
+                    .await;
+                match &result {
+                    Err(e) if is_sequence_mismatch(e) => {
+                        // Our sequence can be behind the chain, e.g. after a tx sent by
+                        // another process, or ahead of it when a broadcast tx was dropped
+                        match (expected_sequence(&e.to_string()), account.as_mut()) {
+                            (Some(expected), Some(current)) => {
+                                tracing::warn!(
+                                    "Account sequence mismatch, using the expected {}: {}",
+                                    expected,
+                                    e
+                                );
+                                current.sequence = expected;
+                            }
+                            _ => {
+                                tracing::warn!(
+                                    "Account sequence mismatch, querying it again: {}",
+                                    e
+                                );
+                                account = None;
+                            }
+                        }
+                    }
+                    _ => break,
+                }
+            }
+
+            match result {
+                // The inclusion is awaited separately, so the next tx can be broadcast right away
+                Ok(txhash) => {
+                    let channel = state.channel.clone();
+                    let span = request.span.clone();
+                    state.tasks.spawn(
+                        async move {
+                            let response = wait_for_tx(channel, txhash).await;
+                            let _ = request.reply.send(response.and_then(check_tx_code));
+                        }
+                        .instrument(span),
+                    );
+                }
+                Err(e) => {
+                    let _ = request.reply.send(Err(e));
+                }
+            }
+        }
+    })
+}
+
+/// Signs the tx at the local sequence and broadcasts it, returns the tx hash
+#[tracing::instrument(skip_all, fields(kind = request.kind))]
+async fn broadcast(
+    state: &AppState,
+    daemon: &DaemonAsync,
+    account: &mut Option<BaseAccount>,
+    request: &SignRequest,
+) -> Result<String, ApiError> {
+    let current = match account {
+        Some(account) => account,
+        None => account.insert(query_account(state.channel.clone(), state.sender.clone()).await?),
+    };
+
+    let estimate = estimate_fee(state, current, request.msgs.clone(), request.kind).await?;
+    let timeout_height = latest_height(state.channel.clone()).await? as u64 + TX_TIMEOUT_BLOCKS;
+    let mut tx_builder = TxBuilder::new(TxBuilder::build_body(
+        request.msgs.clone(),
+        None,
+        timeout_height,
+    ));
+    tx_builder
+        .gas_limit(estimate.gas_limit)
+        .fee_amount(estimate.fee_amount)
+        .sequence(current.sequence);
+    let tx = tx_builder.build(&daemon.sender).await?;
+
+    let response = daemon.sender.broadcast_tx(tx).await?;
+    if response.code == WRONG_SEQUENCE_CODE {
+        return Err(ApiError::SequenceMismatch(response.raw_log));
+    }
+    if response.code != 0 {
+        return Err(ApiError::GenericErr(format!(
+            "{} tx rejected: {}",
+            request.kind, response.raw_log
+        )));
+    }
+
+    // The tx passed the mempool checks, it uses the sequence
+    current.sequence += 1;
+    tracing::info!(
+        "Broadcast {} tx {}, next sequence {}",
+        request.kind,
+        response.txhash,
+        current.sequence
+    );
+    Ok(response.txhash)
+}
+
+/// Queries the tx until it's included
+async fn wait_for_tx(channel: GrpcChannel, txhash: String) -> Result<CosmTxResponse, ApiError> {
+    let mut client = TxServiceClient::new(channel);
+    let mut last_error = None;
+    for _ in 0..TX_POLL_ATTEMPTS {
+        // The tx is not found until it's included
+        match client
+            .get_tx(GetTxRequest {
+                hash: txhash.clone(),
+            })
+            .await
+        {
+            Ok(response) => {
+                if let Some(tx_response) = response.into_inner().tx_response {
+                    return Ok(tx_response.into());
+                }
+            }
+            Err(e) => last_error = Some(e),
+        }
+        tokio::time::sleep(TX_POLL_INTERVAL).await;
+    }
+
+    Err(ApiError::GenericErr(format!(
+        "Tx {} was not found: {}",
+        txhash,
+        last_error.map_or_else(|| "no response".to_string(), |e| e.message().to_string())
+    )))
+}
+
+fn is_sequence_mismatch(error: &ApiError) -> bool {
+    match error {
+        ApiError::SequenceMismatch(_) => true,
+        // Simulations and daemon errors only carry the message
+        e => e.to_string().contains("account sequence mismatch"),
+    }
+}
+
+/// Reads the sequence from errors like `account sequence mismatch, expected 42, got 41`
+fn expected_sequence(message: &str) -> Option<u64> {
+    let (_, expected) = message.split_once("expected ")?;
+    expected
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn check_tx_code(response: CosmTxResponse) -> Result<CosmTxResponse, ApiError> {
+    if response.code != 0 {
+        return Err(ApiError::GenericErr(format!(
+            "Tx {} failed: {}",
+            response.txhash, response.raw_log
+        )));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_expected_sequence_of_the_node_error() {
+        let error = ApiError::SequenceMismatch(
+            "account sequence mismatch, expected 42, got 41: incorrect account sequence"
+                .to_string(),
+        );
+        assert!(is_sequence_mismatch(&error));
+        assert_eq!(expected_sequence(&error.to_string()), Some(42));
+    }
+
+    #[test]
+    fn reads_the_expected_sequence_of_a_failed_simulation() {
+        let error = ApiError::SimulationFailed(
+            "status: Unknown, message: \"account sequence mismatch, expected 1234, got 1233: \
+             incorrect account sequence [cosmos/cosmos-sdk@v0.47.5/x/auth/ante/sigverify.go:269] \
+             With gas wanted: '0' and gas used: '48542' : unknown request\""
+                .to_string(),
+        );
+        assert!(is_sequence_mismatch(&error));
+        assert_eq!(expected_sequence(&error.to_string()), Some(1234));
+    }
+
+    #[test]
+    fn ignores_other_errors() {
+        let error = ApiError::GenericErr("insufficient fees; got: 10uluna".to_string());
+        assert!(!is_sequence_mismatch(&error));
+        assert_eq!(expected_sequence(&error.to_string()), None);
+    }
+}
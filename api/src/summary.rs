@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::Json;
+use cosmwasm_std::Uint128;
+use entities::events_tx;
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect};
+
+use crate::error::ApiError;
+use crate::fee_grants::cached_simulate_grant;
+use crate::tx_indexer::cached_tx_total;
+use crate::types::summary::{AddressSummary, DenomDeposits, IndexerFreshness};
+use crate::AppState;
+
+/// Everything the frontend needs to display the onboarding status of an address
+#[utoipa::path(
+    get,
+    path = "/v1/address/{address}/summary",
+    tag = "addresses",
+    params(("address" = String, Path)),
+    responses((status = 200, body = AddressSummary))
+)]
+pub async fn get_address_summary(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<AddressSummary>, ApiError> {
+    let (deposits, indexed, total, fee_grant) = futures::try_join!(
+        async {
+            // Only the columns that are summed, the tx logs are large
+            Ok::<_, ApiError>(
+                events_tx::Entity::find()
+                    .select_only()
+                    .columns([
+                        events_tx::Column::Denom,
+                        events_tx::Column::KadoAmount,
+                        events_tx::Column::Executed,
+                        events_tx::Column::Timestamp,
+                    ])
+                    .filter(events_tx::Column::Address.eq(address.clone()))
+                    .filter(events_tx::Column::KadoAmount.is_not_null())
+                    .into_tuple::<(Option<String>, Option<String>, i8, String)>()
+                    .all(&state.db)
+                    .await?,
+            )
+        },
+        async {
+            Ok::<_, ApiError>(
+                events_tx::Entity::find()
+                    .filter(events_tx::Column::Address.eq(address.clone()))
+                    .count(&state.db)
+                    .await?,
+            )
+        },
+        cached_tx_total(&state, &address),
+        cached_simulate_grant(&state, address.clone()),
+    )?;
+
+    let last_deposit_at = deposits
+        .iter()
+        .map(|(_, _, _, timestamp)| timestamp.clone())
+        .max();
+
+    let mut per_denom: BTreeMap<String, DenomDeposits> = BTreeMap::new();
+    for (denom, kado_amount, executed, _) in deposits {
+        let denom = denom.unwrap_or_default();
+        let amount = kado_amount
+            .as_deref()
+            .map(Uint128::from_str)
+            .transpose()?
+            .unwrap_or_default();
+
+        let summary = per_denom.entry(denom.clone()).or_insert(DenomDeposits {
+            denom,
+            ..Default::default()
+        });
+        summary.deposit_count += 1;
+        summary.total += amount;
+        if executed == 0 {
+            summary.pending += amount;
+        }
+    }
+
+    Ok(Json(AddressSummary {
+        address,
+        deposits: per_denom.into_values().collect(),
+        last_deposit_at,
+        fee_grant,
+        indexer: IndexerFreshness {
+            indexed,
+            total,
+            behind: total.saturating_sub(indexed),
+        },
+    }))
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, Grant};
+use cosmos_sdk_proto::Any;
+use entities::{events_tx, fee_grant};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use tokio::task::JoinHandle;
+
+use crate::cache::fee_grant_key;
+use crate::db_helpers::GRANT_STATUS_ACTIVE;
+use crate::error::ApiError;
+use crate::fee_grants::{get_grants_by_granter, revoke_many};
+use crate::index_lock::with_job_lease;
+use crate::types::sweeper::{SweepCandidate, SweepReason, SweepReport};
+use crate::AppState;
+
+const SWEEPER_LEASE: &str = "sweeper";
+
+/// Sweeps the grants periodically, if enabled
+pub fn spawn_sweeper(state: Arc<AppState>) -> Option<JoinHandle<()>> {
+    if !state.config.features.sweeper {
+        return None;
+    }
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(state.config.sweeper.interval());
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutdown.cancelled() => return,
+            }
+            // Replicas would otherwise revoke the same grants, the ones that find the lease taken
+            // wait for the running sweep and skip theirs
+            let swept = with_job_lease(state.cache.redis(), &state.db, SWEEPER_LEASE, async {
+                let report = sweep(&state, state.config.sweeper.dry_run).await?;
+                tracing::info!(
+                    "Sweep (dry run: {}) found {} of {} grants to revoke, {} txs sent, {} errors",
+                    report.dry_run,
+                    report.candidates.len(),
+                    report.grants,
+                    report.revoke_txs.len(),
+                    report.errors.len()
+                );
+                Ok(())
+            })
+            .await;
+            if let Err(e) = swept {
+                tracing::error!("Sweep failed: {}", e);
+            }
+        }
+    }))
+}
+
+/// Finds the grants to reclaim and revokes them unless in dry run
+pub async fn sweep(state: &AppState, dry_run: bool) -> Result<SweepReport, ApiError> {
+    let grants = get_grants_by_granter(state.channel.clone(), state.sender.clone()).await?;
+    let now = Utc::now();
+
+    let mut candidates = vec![];
+    for grant in &grants {
+        if let Some(reason) =
+            classify(grant, now, state.config.sweeper.idle_after(), &state.db).await?
+        {
+            candidates.push(SweepCandidate {
+                grantee: grant.grantee.clone(),
+                reason,
+            });
+        }
+    }
+
+    let mut report = SweepReport {
+        dry_run,
+        grants: grants.len(),
+        candidates,
+        ..Default::default()
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    for batch in report.candidates.chunks(state.config.sweeper.batch_size) {
+        let grantees = batch
+            .iter()
+            .map(|candidate| candidate.grantee.clone())
+            .collect::<Vec<_>>();
+        match revoke_many(state, grantees.clone()).await {
+            Ok(tx_hash) => report.revoke_txs.push(tx_hash),
+            // A single bad grant fails the whole tx, the others are revoked one by one
+            Err(e) if grantees.len() > 1 => {
+                tracing::warn!(
+                    "Could not revoke {:?} at once, revoking them one by one: {}",
+                    grantees,
+                    e
+                );
+                for grantee in &grantees {
+                    match revoke_many(state, vec![grantee.clone()]).await {
+                        Ok(tx_hash) => report.revoke_txs.push(tx_hash),
+                        Err(e) => {
+                            tracing::error!("Could not revoke {}: {}", grantee, e);
+                            report.errors.push(format!("{}: {}", grantee, e));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!("Could not revoke {:?}: {}", grantees, e);
+                report.errors.push(e.to_string());
+            }
+        }
+        for grantee in &grantees {
+            state
+                .cache
+                .invalidate(&fee_grant_key(&state.sender, grantee))
+                .await;
+        }
+    }
+
+    Ok(report)
+}
+
+async fn classify(
+    grant: &Grant,
+    now: DateTime<Utc>,
+    idle_after: chrono::Duration,
+    db: &DatabaseConnection,
+) -> Result<Option<SweepReason>, ApiError> {
+    let expiration = grant
+        .allowance
+        .as_ref()
+        .and_then(|allowance| Any::to_msg::<BasicAllowance>(allowance).ok())
+        .and_then(|allowance| allowance.expiration)
+        .and_then(|expiration| {
+            DateTime::from_timestamp(expiration.seconds, expiration.nanos.max(0) as u32)
+        });
+    if expiration.is_some_and(|expiration| expiration <= now) {
+        return Ok(Some(SweepReason::Expired));
+    }
+
+    let deposits = events_tx::Entity::find()
+        .filter(events_tx::Column::Address.eq(grant.grantee.clone()))
+        .filter(events_tx::Column::KadoAmount.is_not_null())
+        .all(db)
+        .await?;
+
+    // The grant was given for deposits that are all executed now
+    let granted_deposits = deposits
+        .iter()
+        .filter(|deposit| deposit.has_fee_grant != 0)
+        .collect::<Vec<_>>();
+    if !granted_deposits.is_empty() && granted_deposits.iter().all(|d| d.executed != 0) {
+        return Ok(Some(SweepReason::Used));
+    }
+
+    // Grants issued before the ledger existed only have their deposits as activity
+    let last_grant = fee_grant::Entity::find()
+        .filter(fee_grant::Column::Grantee.eq(grant.grantee.clone()))
+        .filter(fee_grant::Column::Status.eq(GRANT_STATUS_ACTIVE))
+        .order_by_desc(fee_grant::Column::GrantedAt)
+        .one(db)
+        .await?
+        .map(|grant| grant.granted_at);
+    let last_deposit = deposits
+        .iter()
+        .filter_map(|deposit| DateTime::parse_from_rfc3339(&deposit.timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .max();
+    let last_activity = last_grant.max(last_deposit);
+    if last_activity.map_or(true, |last_activity| last_activity + idle_after <= now) {
+        return Ok(Some(SweepReason::Idle));
+    }
+
+    Ok(None)
+}
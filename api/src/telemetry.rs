@@ -0,0 +1,168 @@
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{HeaderName, Request};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{trace, Resource};
+use tracing::Span;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::config::TelemetryConfig;
+use crate::error::ApiError;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Installs the global subscriber, logs are filtered with `RUST_LOG`
+pub fn init_tracing(config: &TelemetryConfig) -> Result<(), ApiError> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+
+    let fmt_layer = if config.json_logs {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer().boxed()
+    };
+
+    let otlp_layer = config
+        .otlp_endpoint
+        .as_ref()
+        .map(|endpoint| {
+            let tracer = otlp_tracer(endpoint, &config.service_name)?;
+            Ok::<_, ApiError>(tracing_opentelemetry::layer().with_tracer(tracer))
+        })
+        .transpose()?;
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otlp_layer)
+        .try_init()
+        .map_err(|e| ApiError::GenericErr(format!("Could not install the logger: {}", e)))
+}
+
+/// Tracer exporting the spans in batches to the OTLP collector, installed as the global provider
+fn otlp_tracer(endpoint: &str, service_name: &str) -> Result<trace::Tracer, ApiError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .map_err(|e| ApiError::GenericErr(format!("Could not start OTLP export: {}", e)))
+}
+
+/// Sends the spans that are not exported yet
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Span of an HTTP request, with the id set by the request id layer
+pub fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        route,
+        request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use opentelemetry::trace::Tracer;
+    use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{
+        TraceService, TraceServiceServer,
+    };
+    use opentelemetry_proto::tonic::collector::trace::v1::{
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    };
+    use opentelemetry_proto::tonic::common::v1::any_value::Value;
+    use otlp_tonic::{Request, Response, Status};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use super::*;
+
+    /// Collector forwarding what it receives to the test
+    struct StubCollector(mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+    #[otlp_tonic::async_trait]
+    impl TraceService for StubCollector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+            let _ = self.0.send(request.into_inner());
+            Ok(Response::new(ExportTraceServiceResponse::default()))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, mut exported) = mpsc::unbounded_channel();
+        tokio::spawn(
+            otlp_tonic::transport::Server::builder()
+                .add_service(TraceServiceServer::new(StubCollector(sender)))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let tracer = otlp_tracer(&endpoint, "onboarding-test").unwrap();
+        tracer.in_span("grant_fee_to", |_| {});
+        // Flushes the pending batch, it blocks until the export is done
+        tokio::task::spawn_blocking(shutdown_tracing).await.unwrap();
+
+        let request = tokio::time::timeout(Duration::from_secs(10), exported.recv())
+            .await
+            .expect("no export received")
+            .unwrap();
+        let resource_spans = &request.resource_spans[0];
+        let service_name = resource_spans
+            .resource
+            .iter()
+            .flat_map(|resource| &resource.attributes)
+            .find(|attribute| attribute.key == "service.name")
+            .and_then(|attribute| attribute.value.as_ref()?.value.clone());
+        assert_eq!(
+            service_name,
+            Some(Value::StringValue("onboarding-test".to_string()))
+        );
+        let span_names = resource_spans
+            .scope_spans
+            .iter()
+            .flat_map(|scope| &scope.spans)
+            .map(|span| span.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(span_names, ["grant_fee_to"]);
+    }
+}
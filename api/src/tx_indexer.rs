@@ -1,9 +1,19 @@
+use std::str::FromStr;
 use std::sync::Arc;
 
+use crate::cache::tx_total_key;
+use crate::config::IndexerConfig;
 use crate::db_helpers::add_txs_to_db;
 use crate::error::ApiError;
-use crate::{AppState, PAGINATION_LIMIT};
-use axum::extract::{Path, State};
+use crate::events::AddressEvent;
+use crate::grpc::GrpcChannel;
+use crate::index_lock::with_index_lease;
+use crate::kado::reconcile_pending_orders;
+use crate::types::txs::{
+    timestamp_bound, IndexedDeposit, SortOrder, TxCursor, TxDto, TxsPage, TxsQuery,
+};
+use crate::AppState;
+use axum::extract::{Path, Query, State};
 use axum::Json;
 use cosmos_sdk_proto::cosmos::tx::v1beta1::service_client::ServiceClient;
 use cosmos_sdk_proto::cosmos::tx::v1beta1::{GetTxsEventRequest, GetTxsEventResponse, OrderBy};
@@ -11,32 +21,46 @@ use cosmos_sdk_proto::cosmos::tx::v1beta1::{GetTxsEventRequest, GetTxsEventRespo
 use entities::events_tx;
 use futures::future::try_join_all;
 use redis_serde_json::RedisJsonValue;
+use sea_orm::sea_query::Expr;
 use sea_orm::{
-    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait,
 };
 use serde::{Deserialize, Serialize};
-use tonic::transport::Channel;
+use tokio_util::sync::CancellationToken;
 #[derive(Default, Serialize, Deserialize, RedisJsonValue)]
 pub struct EventsStatus {
     current_page: u64,
 }
 
-/// Gets the transactions on the events page
+/// Gets the transactions on the events page, latest first
 async fn get_last_txs(
-    channel: Channel,
+    channel: GrpcChannel,
+    events: Vec<String>,
+    page: u64,
+    limit: u64,
+) -> Result<GetTxsEventResponse, ApiError> {
+    get_txs_event(channel, events, page, limit, OrderBy::Desc).await
+}
+
+/// Gets the transactions on the events page
+pub(crate) async fn get_txs_event(
+    channel: GrpcChannel,
     events: Vec<String>,
     page: u64,
+    limit: u64,
+    order_by: OrderBy,
 ) -> Result<GetTxsEventResponse, ApiError> {
     let mut client = ServiceClient::new(channel);
-    log::info!("Fetching page {}", page);
+    tracing::info!("Fetching page {}", page);
 
     #[allow(deprecated)]
     let request = GetTxsEventRequest {
         events: events.clone(),
         page,
-        limit: PAGINATION_LIMIT,
+        limit,
         pagination: None, // This is not used, so good.
-        order_by: OrderBy::Desc.into(),
+        order_by: order_by.into(),
     };
 
     let tx_result = client.get_txs_event(request.clone()).await?.into_inner();
@@ -44,33 +68,77 @@ async fn get_last_txs(
     Ok(tx_result)
 }
 
-fn events_from_address(address: &String) -> Vec<String> {
+pub(crate) fn events_from_address(address: &String) -> Vec<String> {
     vec![format!("fungible_token_packet.receiver='{}'", address)]
 }
 
+/// Indexes the new txs of an address and returns the new deposits
+#[tracing::instrument(skip(channel, config, shutdown, db))]
 pub async fn fetch_new_txs(
     address: String,
-    channel: Channel,
+    channel: GrpcChannel,
+    config: &IndexerConfig,
+    shutdown: &CancellationToken,
     db: &DatabaseConnection,
-) -> Result<(), ApiError> {
-    println!("address {}", address);
-    let events = events_from_address(&address);
-
+) -> Result<Vec<IndexedDeposit>, ApiError> {
     // First we query the existing transactions
     let current_events_card = events_tx::Entity::find()
         .filter(events_tx::Column::Address.eq(address.clone()))
         .count(db)
         .await?;
 
-    let mut current_page = current_events_card / PAGINATION_LIMIT + 1;
+    fetch_txs_from_page(
+        address,
+        channel,
+        config,
+        shutdown,
+        db,
+        current_events_card / config.page_size + 1,
+        current_events_card,
+    )
+    .await
+}
+
+/// Walks all the tx pages of an address again, saving the txs that were missed
+#[tracing::instrument(skip(channel, config, shutdown, db))]
+pub async fn refetch_all_txs(
+    address: String,
+    channel: GrpcChannel,
+    config: &IndexerConfig,
+    shutdown: &CancellationToken,
+    db: &DatabaseConnection,
+) -> Result<Vec<IndexedDeposit>, ApiError> {
+    fetch_txs_from_page(address, channel, config, shutdown, db, 1, 0).await
+}
 
-    let mut local_count = current_events_card;
+/// Saves the txs that are not indexed yet, from `first_page` until the last page.
+/// `local_count` is the number of txs known to be before `first_page`.
+/// On shutdown, it stops after the current page, the next run starts from the saved count
+async fn fetch_txs_from_page(
+    address: String,
+    channel: GrpcChannel,
+    config: &IndexerConfig,
+    shutdown: &CancellationToken,
+    db: &DatabaseConnection,
+    first_page: u64,
+    local_count: u64,
+) -> Result<Vec<IndexedDeposit>, ApiError> {
+    let events = events_from_address(&address);
+    let mut current_page = first_page;
+    let mut local_count = local_count;
+    let mut deposits = vec![];
     // Now we get all new txs until there is no more transactions
     loop {
-        let tx_result = get_last_txs(channel.clone(), events.clone(), current_page).await?;
+        let tx_result = get_last_txs(
+            channel.clone(),
+            events.clone(),
+            current_page,
+            config.page_size,
+        )
+        .await?;
         let fetched_txs = tx_result.tx_responses;
 
-        log::debug!(
+        tracing::debug!(
             "Fetched tx hashes : {:?} - {events:?}",
             fetched_txs
                 .iter()
@@ -97,7 +165,7 @@ pub async fn fetch_new_txs(
             .filter_map(|(tx, filter)| if filter { Some(tx) } else { None })
             .collect();
 
-        log::debug!(
+        tracing::debug!(
             "New tx hashes : {:?}",
             new_txs
                 .iter()
@@ -106,13 +174,21 @@ pub async fn fetch_new_txs(
         );
 
         let temp_count = local_count + new_txs.len() as u64;
-        add_txs_to_db(address.clone(), new_txs, db).await?;
+        deposits.extend(add_txs_to_db(address.clone(), new_txs, &config.deposit_denoms, db).await?);
 
-        if temp_count >= tx_result.total {
-            // If the number of element matches the total :
+        if temp_count >= tx_result.total || current_page * config.page_size >= tx_result.total {
+            // If the number of element matches the total, or if this was the last page :
             // - We don't increment the page number as a page might be partially full
             // - We stop querying new transaction
-            return Ok(());
+            return Ok(deposits);
+        }
+        if shutdown.is_cancelled() {
+            tracing::info!(
+                "Stopping the indexing of {} at page {}",
+                address,
+                current_page
+            );
+            return Ok(deposits);
         }
 
         // In any other case, we update the underlying object and try again for other transactions
@@ -121,22 +197,207 @@ pub async fn fetch_new_txs(
     }
 }
 
-// Fetches locally saved transactions
+/// Indexes an address and notifies the subscribers of the new deposits.
+/// Concurrent calls for the same address share a single indexing job
+pub async fn run_indexer(state: Arc<AppState>, address: String) -> Result<(), ApiError> {
+    run_locked_indexer(state, address, false).await
+}
+
+/// Same as [`run_indexer`], but walks all the pages instead of starting from the saved count
+pub async fn run_reindexer(state: Arc<AppState>, address: String) -> Result<(), ApiError> {
+    run_locked_indexer(state, address, true).await
+}
+
+async fn run_locked_indexer(
+    state: Arc<AppState>,
+    address: String,
+    from_start: bool,
+) -> Result<(), ApiError> {
+    let job_state = state.clone();
+    let job_address = address.clone();
+    let job_key = if from_start {
+        format!("reindex:{}", address)
+    } else {
+        address.clone()
+    };
+    state
+        .index_locks
+        .run_once(&job_key, async move {
+            with_index_lease(
+                job_state.cache.redis(),
+                &job_state.db,
+                &job_address,
+                index_and_publish(&job_state, job_address.clone(), from_start),
+            )
+            .await
+        })
+        .await
+}
+
+async fn index_and_publish(
+    state: &AppState,
+    address: String,
+    from_start: bool,
+) -> Result<(), ApiError> {
+    let deposits = if from_start {
+        refetch_all_txs(
+            address.clone(),
+            state.channel.clone(),
+            &state.config.indexer,
+            &state.shutdown,
+            &state.db,
+        )
+        .await?
+    } else {
+        fetch_new_txs(
+            address.clone(),
+            state.channel.clone(),
+            &state.config.indexer,
+            &state.shutdown,
+            &state.db,
+        )
+        .await?
+    };
+    // The indexer just queried the chain, the cached total might be outdated
+    state.cache.invalidate(&tx_total_key(&address)).await;
+    publish_deposits(state, address, deposits).await
+}
+
+/// Notifies the subscribers of deposits that were just saved
+pub(crate) async fn publish_deposits(
+    state: &AppState,
+    address: String,
+    deposits: Vec<IndexedDeposit>,
+) -> Result<(), ApiError> {
+    if deposits.is_empty() {
+        return Ok(());
+    }
+    for deposit in deposits {
+        state
+            .metrics
+            .deposits_indexed
+            .with_label_values(&[&deposit.denom])
+            .inc();
+        state
+            .events
+            .publish(AddressEvent::deposit_detected(address.clone(), deposit));
+    }
+
+    // Kado orders might have been received before their deposit was indexed
+    reconcile_pending_orders(&state.db, Some(address)).await
+}
+
+// Fetches locally saved deposits, filtered and paginated
+#[utoipa::path(
+    get,
+    path = "/v1/txs/{address}",
+    tag = "txs",
+    params(("address" = String, Path), TxsQuery),
+    responses(
+        (status = 200, body = TxsPage),
+        (status = 400, description = "Invalid cursor or date", body = String)
+    )
+)]
 pub async fn get_txs(
     Path(address): Path<String>,
+    Query(query): Query<TxsQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<events_tx::Model>>, ApiError> {
-    let all_txs = events_tx::Entity::find()
+) -> Result<Json<TxsPage>, ApiError> {
+    let pagination = &state.config.pagination;
+    let limit = query
+        .limit
+        .unwrap_or(pagination.txs_default_page_size)
+        .clamp(1, pagination.txs_max_page_size);
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(TxCursor::from_str)
+        .transpose()?;
+
+    let from = query.from.as_deref().map(timestamp_bound).transpose()?;
+    let to = query.to.as_deref().map(timestamp_bound).transpose()?;
+
+    let filtered_txs = events_tx::Entity::find()
         .filter(events_tx::Column::Address.eq(address.clone()))
         .filter(events_tx::Column::KadoAmount.is_not_null())
-        .filter(events_tx::Column::Executed.eq(false))
-        .order_by_desc(events_tx::Column::Timestamp)
-        .all(&state.db)
-        .await?;
+        .apply_if(query.executed, |q, executed| {
+            q.filter(events_tx::Column::Executed.eq(executed))
+        })
+        .apply_if(from, |q, from| {
+            q.filter(events_tx::Column::Timestamp.gte(from))
+        })
+        .apply_if(to, |q, to| q.filter(events_tx::Column::Timestamp.lt(to)))
+        .apply_if(query.denom, |q, denom| {
+            q.filter(events_tx::Column::Denom.eq(denom))
+        })
+        .apply_if(query.has_fee_grant, |q, has_fee_grant| {
+            q.filter(events_tx::Column::HasFeeGrant.eq(has_fee_grant))
+        })
+        .apply_if(query.min_amount, |q, min_amount| {
+            // Amounts are stored as strings, they need to be compared as numbers
+            q.filter(Expr::cust_with_values(
+                "CAST(`kado_amount` AS DECIMAL(65, 0)) >= CAST(? AS DECIMAL(65, 0))",
+                [min_amount.to_string()],
+            ))
+        });
+
+    let total = filtered_txs.clone().count(&state.db).await?;
+
+    let page_query = match query.order {
+        SortOrder::Asc => filtered_txs
+            .apply_if(cursor, |q, cursor| {
+                q.filter(
+                    Condition::any()
+                        .add(events_tx::Column::Timestamp.gt(cursor.timestamp.clone()))
+                        .add(
+                            Condition::all()
+                                .add(events_tx::Column::Timestamp.eq(cursor.timestamp))
+                                .add(events_tx::Column::Id.gt(cursor.id)),
+                        ),
+                )
+            })
+            .order_by_asc(events_tx::Column::Timestamp)
+            .order_by_asc(events_tx::Column::Id),
+        SortOrder::Desc => filtered_txs
+            .apply_if(cursor, |q, cursor| {
+                q.filter(
+                    Condition::any()
+                        .add(events_tx::Column::Timestamp.lt(cursor.timestamp.clone()))
+                        .add(
+                            Condition::all()
+                                .add(events_tx::Column::Timestamp.eq(cursor.timestamp))
+                                .add(events_tx::Column::Id.lt(cursor.id)),
+                        ),
+                )
+            })
+            .order_by_desc(events_tx::Column::Timestamp)
+            .order_by_desc(events_tx::Column::Id),
+    };
 
-    Ok(Json(all_txs))
+    // We fetch one more element to know if there is a next page
+    let mut txs = page_query.limit(limit + 1).all(&state.db).await?;
+    let next_cursor = if txs.len() as u64 > limit {
+        txs.truncate(limit as usize);
+        txs.last().map(|tx| TxCursor::from(tx).to_string())
+    } else {
+        None
+    };
+
+    Ok(Json(TxsPage {
+        txs: txs.into_iter().map(TxDto::from).collect(),
+        next_cursor,
+        total,
+    }))
 }
 
+/// Number of txs saved for the address
+#[utoipa::path(
+    get,
+    path = "/v1/tx-count/{address}",
+    tag = "txs",
+    params(("address" = String, Path)),
+    responses((status = 200, body = u64))
+)]
 pub async fn get_tx_count(
     Path(address): Path<String>,
     State(state): State<Arc<AppState>>,
@@ -149,13 +410,41 @@ pub async fn get_tx_count(
     Ok(Json(tx_count))
 }
 
+/// Number of txs the chain reports for this address
+pub async fn query_tx_total(channel: GrpcChannel, address: &String) -> Result<u64, ApiError> {
+    let events = events_from_address(address);
+
+    // Only the total is needed
+    let tx_result = get_last_txs(channel, events, 1, 1).await?;
+
+    Ok(tx_result.total)
+}
+
+/// Same as [`query_tx_total`], cached for a short time
+pub async fn cached_tx_total(state: &AppState, address: &String) -> Result<u64, ApiError> {
+    state
+        .cache
+        .get_or_insert(
+            &tx_total_key(address),
+            state.cache.ttls.tx_total,
+            query_tx_total(state.channel.clone(), address),
+        )
+        .await
+}
+
+/// Number of txs the chain reports for the address
+#[utoipa::path(
+    get,
+    path = "/v1/tx-total/{address}",
+    tag = "txs",
+    params(("address" = String, Path)),
+    responses((status = 200, body = u64))
+)]
 pub async fn get_tx_total(
     Path(address): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<u64>, ApiError> {
-    let events = events_from_address(&address);
-
-    let tx_result = get_last_txs(state.channel.clone(), events.clone(), 1).await?;
+    let total = cached_tx_total(&state, &address).await?;
 
-    Ok(Json(tx_result.total))
+    Ok(Json(total))
 }
@@ -0,0 +1,28 @@
+use sea_orm::FromQueryResult;
+use serde::{Deserialize, Serialize};
+
+/// API key allowed to use the admin API, the name is recorded in the audit log
+pub struct AdminKey {
+    pub name: String,
+    pub key: String,
+}
+
+/// Admin key that authenticated the request
+#[derive(Clone)]
+pub struct AdminIdentity {
+    pub key_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct GrantsQuery {
+    pub address: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, FromQueryResult)]
+pub struct IndexerCursor {
+    pub address: String,
+    /// Number of txs indexed, the next indexing starts from the page containing this tx
+    pub indexed: i64,
+    pub last_timestamp: Option<String>,
+}
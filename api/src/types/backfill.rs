@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use entities::backfill_job;
+use serde::{Deserialize, Serialize};
+
+/// What the segments of a backfill job scan
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackfillTargetKind {
+    /// Every tx received by the address
+    Address,
+    /// Every transfer of the denom, to any address
+    Denom,
+}
+
+impl BackfillTargetKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackfillTargetKind::Address => "address",
+            BackfillTargetKind::Denom => "denom",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "address" => Some(BackfillTargetKind::Address),
+            "denom" => Some(BackfillTargetKind::Denom),
+            _ => None,
+        }
+    }
+}
+
+/// Status of a backfill job, and of each of its segments
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackfillStatus {
+    Pending,
+    /// Being scanned, or interrupted by a stop and resumed on the next start
+    Running,
+    Completed,
+    /// Some segments failed, they are retried when the job is resumed
+    Failed,
+}
+
+impl BackfillStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackfillStatus::Pending => "pending",
+            BackfillStatus::Running => "running",
+            BackfillStatus::Completed => "completed",
+            BackfillStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Range and targets of a new backfill job.
+/// Heights take precedence over dates, the range defaults to the whole chain
+#[derive(Deserialize, Default)]
+pub struct BackfillRequest {
+    /// Addresses to scan, all the receivers of the deposit denoms when empty
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    pub from_height: Option<i64>,
+    pub to_height: Option<i64>,
+    /// Inclusive lower bound on the block time
+    pub from_date: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the block time
+    pub to_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct BackfillProgress {
+    #[serde(flatten)]
+    pub job: backfill_job::Model,
+    /// Share of the segments completed, between 0 and 100
+    pub percent: f64,
+    /// Errors of the failed segments
+    pub errors: Vec<String>,
+}
@@ -1,43 +1,120 @@
-use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, Grant};
+use chrono::{DateTime, Utc};
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, Grant, PeriodicAllowance};
 use cosmwasm_std::{Coin, StdError, Uint128};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+/// Converts a protobuf timestamp
+pub fn datetime_from_proto(seconds: i64, nanos: i32) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(seconds, nanos.max(0) as u32)
+}
+
+fn coins_from_proto(
+    coins: Vec<cosmos_sdk_proto::cosmos::base::v1beta1::Coin>,
+) -> Result<Vec<Coin>, StdError> {
+    coins
+        .into_iter()
+        .map(|s| {
+            Ok::<_, StdError>(Coin {
+                denom: s.denom,
+                amount: Uint128::from_str(&s.amount)?,
+            })
+        })
+        .collect()
+}
+
+/// Documents `cosmwasm_std::Coin`, the amount is an integer string
+#[derive(ToSchema)]
+#[schema(as = Coin)]
+pub struct CoinSchema {
+    #[schema(example = "uluna")]
+    pub denom: String,
+    #[schema(example = "100000")]
+    pub amount: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct CosmosBasicAllowance {
+    /// What's left to spend, empty when the allowance is unlimited
+    #[schema(value_type = Vec<CoinSchema>)]
     pub spend_limit: Vec<cosmwasm_std::Coin>,
+    #[serde(default)]
+    pub expiration: Option<DateTime<Utc>>,
 }
 impl TryInto<CosmosBasicAllowance> for BasicAllowance {
     fn try_into(self) -> Result<CosmosBasicAllowance, Self::Error> {
         Ok(CosmosBasicAllowance {
-            spend_limit: self
-                .spend_limit
-                .into_iter()
-                .map(|s| {
-                    Ok::<_, StdError>(Coin {
-                        denom: s.denom,
-                        amount: Uint128::from_str(&s.amount)?,
-                    })
-                })
-                .collect::<Result<Vec<_>, _>>()?,
+            spend_limit: coins_from_proto(self.spend_limit)?,
+            expiration: self
+                .expiration
+                .and_then(|e| datetime_from_proto(e.seconds, e.nanos)),
+        })
+    }
+
+    type Error = StdError;
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CosmosPeriodicAllowance {
+    pub basic: Option<CosmosBasicAllowance>,
+    pub period_secs: i64,
+    /// Amount that can be spent in each period
+    #[schema(value_type = Vec<CoinSchema>)]
+    pub period_spend_limit: Vec<Coin>,
+    /// Amount left to spend in the current period
+    #[schema(value_type = Vec<CoinSchema>)]
+    pub period_can_spend: Vec<Coin>,
+    /// When `period_can_spend` goes back to `period_spend_limit`
+    pub period_reset: Option<DateTime<Utc>>,
+}
+impl TryInto<CosmosPeriodicAllowance> for PeriodicAllowance {
+    fn try_into(self) -> Result<CosmosPeriodicAllowance, Self::Error> {
+        Ok(CosmosPeriodicAllowance {
+            basic: self.basic.map(TryInto::try_into).transpose()?,
+            period_secs: self.period.map(|p| p.seconds).unwrap_or_default(),
+            period_spend_limit: coins_from_proto(self.period_spend_limit)?,
+            period_can_spend: coins_from_proto(self.period_can_spend)?,
+            period_reset: self
+                .period_reset
+                .and_then(|r| datetime_from_proto(r.seconds, r.nanos)),
         })
     }
 
     type Error = StdError;
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct BasicAllowanceGrant {
     pub granter: String,
     pub grantee: String,
     pub allowance: Option<CosmosBasicAllowance>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PeriodicAllowanceGrant {
+    pub granter: String,
+    pub grantee: String,
+    pub allowance: CosmosPeriodicAllowance,
+}
+
+/// Allowance of a type the API doesn't decode
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RawAllowance {
+    #[schema(example = "/cosmos.feegrant.v1beta1.AllowedMsgAllowance")]
+    pub type_url: String,
+    /// Protobuf encoded allowance, in hex
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CosmosGrant {
     pub granter: String,
     pub grantee: String,
-    pub allowance: Option<(String, Vec<u8>)>,
+    pub allowance: Option<RawAllowance>,
 }
 
 impl From<Grant> for CosmosGrant {
@@ -45,19 +122,47 @@ impl From<Grant> for CosmosGrant {
         CosmosGrant {
             granter: val.granter,
             grantee: val.grantee,
-            allowance: val.allowance.map(|a| (a.type_url, a.value)),
+            allowance: val.allowance.map(|a| RawAllowance {
+                type_url: a.type_url,
+                value: hex::encode(a.value),
+            }),
         }
     }
 }
 
-#[derive(Serialize)]
+/// Allowance given by a granter, tagged by its `type`
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
 pub enum QuerierGrant {
     BasicAllowance(BasicAllowanceGrant),
+    PeriodicAllowance(PeriodicAllowanceGrant),
     AnyAllowance(CosmosGrant),
 }
 
-#[derive(Serialize)]
-pub enum GrantSimulationResult {
-    Present(QuerierGrant),
-    None,
+/// Reset schedule of a periodic allowance
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodicReset {
+    pub period_secs: i64,
+    #[schema(value_type = Vec<CoinSchema>)]
+    pub period_spend_limit: Vec<Coin>,
+    #[schema(value_type = Vec<CoinSchema>)]
+    pub period_can_spend: Vec<Coin>,
+    pub period_reset: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GrantSimulationResult {
+    /// The allowance currently on-chain, if any
+    pub grant: Option<QuerierGrant>,
+    /// What the grantee can still spend per denom, `None` when unlimited or unknown
+    #[schema(value_type = Option<Vec<CoinSchema>>)]
+    pub remaining: Option<Vec<Coin>>,
+    pub expiration: Option<DateTime<Utc>>,
+    pub periodic: Option<PeriodicReset>,
+    /// Deposit txs the remaining fee denom allowance pays for at the current gas price
+    pub estimated_txs_covered: Option<u64>,
+    /// Whether a grant request would replace the current allowance
+    pub would_regrant: bool,
 }
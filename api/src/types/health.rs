@@ -0,0 +1,31 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// Ordered from the best to the worst, a disabled dependency can't make the report worse
+pub enum CheckStatus {
+    /// The dependency is not configured
+    Disabled,
+    Ok,
+    /// Usable, but needs attention
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub latency_ms: u64,
+    /// What the check found, or why it failed
+    pub details: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatusReport {
+    /// The worst status among the checks
+    pub status: CheckStatus,
+    pub checks: Vec<DependencyCheck>,
+}
@@ -0,0 +1,65 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use cosmwasm_std::Decimal;
+use entities::kado_order;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// Order notification sent by Kado when an on-ramp order changes
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KadoOrderWebhook {
+    pub order_id: String,
+    /// Address that receives the funds on Terra
+    pub wallet_address: String,
+    /// Amount of USDC that is sent to the wallet, in USDC (not micro units)
+    #[serde(deserialize_with = "decimal_from_str_or_number")]
+    pub receive_amount: Decimal,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Amounts can be sent either as JSON strings or as JSON numbers
+fn decimal_from_str_or_number<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Text(String),
+        Number(serde_json::Number),
+    }
+
+    let amount = match Amount::deserialize(deserializer)? {
+        Amount::Text(text) => text,
+        Amount::Number(number) => number.to_string(),
+    };
+    Decimal::from_str(&amount).map_err(de::Error::custom)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchStatus {
+    /// No deposit was found for the order yet
+    Unmatched,
+    /// A deposit with the same receiver and amount was found in the time window
+    Matched,
+    /// A deposit was found in the time window, but the amount differs
+    Mismatched,
+}
+
+impl MatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchStatus::Unmatched => "unmatched",
+            MatchStatus::Matched => "matched",
+            MatchStatus::Mismatched => "mismatched",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReconciliationReport {
+    pub unmatched: Vec<kado_order::Model>,
+    pub mismatched: Vec<kado_order::Model>,
+}
@@ -0,0 +1,116 @@
+use cosmos_sdk_proto::cosmos::feegrant::v1beta1::{BasicAllowance, Grant};
+use cosmos_sdk_proto::Any;
+use cosmwasm_std::{Coin, StdError, Uint128};
+use entities::events_tx;
+use entities::log::TxLogs;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Response shapes of the routes before `/v1`, only served by the deprecated aliases.
+// They must not change, new fields go to the `/v1` types
+
+#[derive(Serialize)]
+pub struct LegacyBasicAllowance {
+    pub spend_limit: Vec<Coin>,
+}
+
+impl TryFrom<BasicAllowance> for LegacyBasicAllowance {
+    type Error = StdError;
+
+    fn try_from(allowance: BasicAllowance) -> Result<Self, Self::Error> {
+        Ok(LegacyBasicAllowance {
+            spend_limit: allowance
+                .spend_limit
+                .into_iter()
+                .map(|s| {
+                    Ok::<_, StdError>(Coin {
+                        denom: s.denom,
+                        amount: Uint128::from_str(&s.amount)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct LegacyBasicAllowanceGrant {
+    pub granter: String,
+    pub grantee: String,
+    pub allowance: Option<LegacyBasicAllowance>,
+}
+
+/// The allowance is the type url and the protobuf bytes
+#[derive(Serialize)]
+pub struct LegacyCosmosGrant {
+    pub granter: String,
+    pub grantee: String,
+    pub allowance: Option<(String, Vec<u8>)>,
+}
+
+#[derive(Serialize)]
+pub enum LegacyQuerierGrant {
+    BasicAllowance(LegacyBasicAllowanceGrant),
+    AnyAllowance(LegacyCosmosGrant),
+}
+
+/// Only basic allowances were decoded
+impl TryFrom<Grant> for LegacyQuerierGrant {
+    type Error = StdError;
+
+    fn try_from(grant: Grant) -> Result<Self, Self::Error> {
+        match grant
+            .allowance
+            .as_ref()
+            .map(Any::to_msg::<BasicAllowance>)
+            .transpose()
+        {
+            Ok(allowance) => Ok(LegacyQuerierGrant::BasicAllowance(
+                LegacyBasicAllowanceGrant {
+                    granter: grant.granter,
+                    grantee: grant.grantee,
+                    allowance: allowance.map(TryInto::try_into).transpose()?,
+                },
+            )),
+            Err(_) => Ok(LegacyQuerierGrant::AnyAllowance(LegacyCosmosGrant {
+                granter: grant.granter,
+                grantee: grant.grantee,
+                allowance: grant.allowance.map(|a| (a.type_url, a.value)),
+            })),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub enum LegacyGrantSimulationResult {
+    Present(LegacyQuerierGrant),
+    None,
+}
+
+/// Row of `events_tx` as it was serialised, the columns added since are left out
+#[derive(Serialize)]
+pub struct LegacyTx {
+    pub id: i32,
+    pub address: String,
+    pub tx_hash: String,
+    pub tx_events: TxLogs,
+    pub timestamp: String,
+    pub kado_amount: Option<String>,
+    pub has_fee_grant: i8,
+    pub executed: i8,
+}
+
+impl From<events_tx::Model> for LegacyTx {
+    fn from(tx: events_tx::Model) -> Self {
+        LegacyTx {
+            id: tx.id,
+            address: tx.address,
+            tx_hash: tx.tx_hash,
+            tx_events: tx.tx_events,
+            timestamp: tx.timestamp,
+            kado_amount: tx.kado_amount,
+            has_fee_grant: tx.has_fee_grant,
+            executed: tx.executed,
+        }
+    }
+}
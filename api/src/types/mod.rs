@@ -1 +1,10 @@
+pub mod admin;
+pub mod backfill;
 pub mod grants;
+pub mod health;
+pub mod kado;
+pub mod legacy;
+pub mod summary;
+pub mod sweeper;
+pub mod txs;
+pub mod webhooks;
@@ -0,0 +1,37 @@
+use cosmwasm_std::Uint128;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::grants::GrantSimulationResult;
+
+#[derive(Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DenomDeposits {
+    pub denom: String,
+    pub deposit_count: u64,
+    /// Sum of all the deposits received in this denom
+    #[schema(value_type = String)]
+    pub total: Uint128,
+    /// Sum of the deposits that were not executed yet
+    #[schema(value_type = String)]
+    pub pending: Uint128,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IndexerFreshness {
+    /// Number of txs saved locally for this address
+    pub indexed: u64,
+    /// Number of txs the chain reports for this address
+    pub total: u64,
+    pub behind: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressSummary {
+    pub address: String,
+    pub deposits: Vec<DenomDeposits>,
+    pub last_deposit_at: Option<String>,
+    pub fee_grant: GrantSimulationResult,
+    pub indexer: IndexerFreshness,
+}
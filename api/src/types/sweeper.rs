@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+/// Why a grant is reclaimed, in order of precedence
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepReason {
+    /// The allowance expiration is in the past
+    Expired,
+    /// All the deposits the grant was given for are executed
+    Used,
+    /// No grant nor deposit for the address in a while
+    Idle,
+}
+
+#[derive(Serialize)]
+pub struct SweepCandidate {
+    pub grantee: String,
+    pub reason: SweepReason,
+}
+
+#[derive(Serialize, Default)]
+pub struct SweepReport {
+    pub dry_run: bool,
+    /// Number of grants we own on-chain
+    pub grants: usize,
+    pub candidates: Vec<SweepCandidate>,
+    /// Hashes of the revocation txs, one per batch
+    pub revoke_txs: Vec<String>,
+    /// Batches that failed, they are retried on the next sweep
+    pub errors: Vec<String>,
+}
@@ -0,0 +1,242 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use cosmwasm_std::Uint128;
+use entities::events_tx;
+use entities::log::TxLog;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::ApiError;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Filters and pagination accepted by the `/txs/:address` endpoint
+#[derive(Deserialize, Default, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query, rename_all = "camelCase")]
+pub struct TxsQuery {
+    /// Opaque cursor returned as `nextCursor` by the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<u64>,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Inclusive lower bound on the tx timestamp (RFC 3339)
+    pub from: Option<String>,
+    /// Exclusive upper bound on the tx timestamp (RFC 3339)
+    pub to: Option<String>,
+    pub denom: Option<String>,
+    /// Executed and pending deposits are both returned when not set
+    pub executed: Option<bool>,
+    pub has_fee_grant: Option<bool>,
+    #[param(value_type = Option<String>)]
+    pub min_amount: Option<Uint128>,
+}
+
+/// Converts an RFC 3339 date to the format of the saved tx timestamps, so that they can be compared.
+/// Block times have whole seconds, a fractional date is rounded up to the next tx that can follow it
+pub fn timestamp_bound(date: &str) -> Result<String, ApiError> {
+    let date = DateTime::parse_from_rfc3339(date)
+        .map_err(|e| ApiError::InvalidQuery(format!("invalid date {}: {}", date, e)))?
+        .with_timezone(&Utc);
+    let seconds = date.timestamp() + i64::from(date.timestamp_subsec_nanos() > 0);
+    let date = DateTime::from_timestamp(seconds, 0)
+        .ok_or_else(|| ApiError::InvalidQuery(format!("date out of range {}", date)))?;
+    Ok(date.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Indexed tx as returned by the API, kept apart from the `events_tx` entity
+/// so that the table can change without breaking the clients
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TxDto {
+    pub address: String,
+    pub tx_hash: String,
+    /// Logs of the tx, as returned by the node
+    pub tx_events: Vec<TxLogDto>,
+    pub timestamp: String,
+    /// Amount received, set for deposits only
+    pub kado_amount: Option<String>,
+    /// Whether fees were granted for this deposit
+    pub has_fee_grant: bool,
+    pub executed: bool,
+    pub denom: Option<String>,
+    /// Sender of the deposit on the source chain
+    pub sender: Option<String>,
+}
+
+impl From<events_tx::Model> for TxDto {
+    fn from(tx: events_tx::Model) -> Self {
+        TxDto {
+            address: tx.address,
+            tx_hash: tx.tx_hash,
+            tx_events: tx.tx_events.0.into_iter().map(TxLogDto::from).collect(),
+            timestamp: tx.timestamp,
+            kado_amount: tx.kado_amount,
+            has_fee_grant: tx.has_fee_grant != 0,
+            executed: tx.executed != 0,
+            denom: tx.denom,
+            sender: tx.sender,
+        }
+    }
+}
+
+/// Log of one message of the tx
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TxLogDto {
+    pub msg_index: u32,
+    pub log: String,
+    pub events: Vec<TxEventDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TxEventDto {
+    #[schema(example = "fungible_token_packet")]
+    pub event_type: String,
+    pub attributes: Vec<TxAttributeDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TxAttributeDto {
+    pub key: String,
+    pub value: String,
+}
+
+impl From<TxLog> for TxLogDto {
+    fn from(log: TxLog) -> Self {
+        TxLogDto {
+            msg_index: log.msg_index,
+            log: log.log,
+            events: log
+                .events
+                .into_iter()
+                .map(|event| TxEventDto {
+                    event_type: event.event_type,
+                    attributes: event
+                        .attributes
+                        .into_iter()
+                        .map(|attribute| TxAttributeDto {
+                            key: attribute.key,
+                            value: attribute.value,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TxsPage {
+    pub txs: Vec<TxDto>,
+    pub next_cursor: Option<String>,
+    /// Number of txs matching the filters, regardless of the cursor
+    pub total: u64,
+}
+
+/// Position of the last returned tx. Txs are sorted by timestamp, the id breaks ties
+pub struct TxCursor {
+    pub timestamp: String,
+    pub id: i32,
+}
+
+impl From<&events_tx::Model> for TxCursor {
+    fn from(tx: &events_tx::Model) -> Self {
+        TxCursor {
+            timestamp: tx.timestamp.clone(),
+            id: tx.id,
+        }
+    }
+}
+
+impl fmt::Display for TxCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.timestamp, self.id)
+    }
+}
+
+impl FromStr for TxCursor {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ApiError::InvalidQuery(format!("invalid cursor {}", s));
+        let (timestamp, id) = s.rsplit_once('_').ok_or_else(invalid)?;
+        Ok(TxCursor {
+            timestamp: timestamp.to_string(),
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Deposit that was just saved by the indexer
+#[derive(Clone, Debug)]
+pub struct IndexedDeposit {
+    pub tx_hash: String,
+    pub denom: String,
+    pub amount: String,
+    pub timestamp: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = TxCursor {
+            timestamp: "2024-01-02T03:04:05Z".to_string(),
+            id: 42,
+        };
+        let parsed = TxCursor::from_str(&cursor.to_string()).unwrap();
+        assert_eq!(parsed.timestamp, "2024-01-02T03:04:05Z");
+        assert_eq!(parsed.id, 42);
+    }
+
+    #[test]
+    fn cursor_id_is_after_the_last_underscore() {
+        let parsed = TxCursor::from_str("some_timestamp_7").unwrap();
+        assert_eq!(parsed.timestamp, "some_timestamp");
+        assert_eq!(parsed.id, 7);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        for cursor in [
+            "",
+            "2024-01-02T03:04:05Z",
+            "2024-01-02T03:04:05Z_",
+            "ts_abc",
+        ] {
+            assert!(matches!(
+                TxCursor::from_str(cursor),
+                Err(ApiError::InvalidQuery(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn timestamp_bounds_use_the_saved_format() {
+        assert_eq!(
+            timestamp_bound("2024-01-02T05:04:05+02:00").unwrap(),
+            "2024-01-02T03:04:05Z"
+        );
+        // No tx of the second before a fractional date can match
+        assert_eq!(
+            timestamp_bound("2024-01-02T05:04:05.123+02:00").unwrap(),
+            "2024-01-02T03:04:06Z"
+        );
+        assert!(matches!(
+            timestamp_bound("yesterday"),
+            Err(ApiError::InvalidQuery(_))
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+/// New webhook subscription
+#[derive(Deserialize)]
+pub struct WebhookSubscriptionRequest {
+    pub url: String,
+    /// Key of the HMAC signature, kept by the receiver to check the deliveries
+    pub secret: String,
+    /// Event types to send, `*` for all of them
+    pub events: Vec<String>,
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Request, State};
+use axum::http::header::LINK;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Json;
+use entities::events_tx;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::error::ApiError;
+use crate::fee_grants::query_allowance;
+use crate::types::legacy::{LegacyGrantSimulationResult, LegacyTx};
+use crate::AppState;
+
+/// Prefix of the current version of the API
+pub const API_V1: &str = "/v1";
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+
+/// Middleware of the unversioned aliases, pointing the clients to the `/v1` route
+pub async fn deprecated_alias(request: Request, next: Next) -> Response {
+    let successor = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        API_V1,
+        request.uri().path()
+    );
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(LINK, link);
+    }
+    response
+}
+
+/// `/fee-grant/:address` before `/v1`: the allowance only, externally tagged
+pub async fn legacy_simulate_grant_fee_to(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<LegacyGrantSimulationResult>, ApiError> {
+    let grant = query_allowance(state.channel.clone(), state.sender.clone(), address).await;
+
+    Ok(Json(match grant {
+        Some(grant) => LegacyGrantSimulationResult::Present(grant.try_into()?),
+        None => LegacyGrantSimulationResult::None,
+    }))
+}
+
+/// `/txs/:address` before `/v1`: every pending deposit, newest first
+pub async fn legacy_get_txs(
+    Path(address): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<LegacyTx>>, ApiError> {
+    let pending = events_tx::Entity::find()
+        .filter(events_tx::Column::Address.eq(address))
+        .filter(events_tx::Column::KadoAmount.is_not_null())
+        .filter(events_tx::Column::Executed.eq(false))
+        .order_by_desc(events_tx::Column::Timestamp)
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(pending.into_iter().map(LegacyTx::from).collect()))
+}
@@ -0,0 +1,292 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use entities::{webhook_delivery, webhook_subscription};
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio_util::task::TaskTracker;
+
+use crate::error::ApiError;
+use crate::events::{AddressEvent, EventBus};
+use crate::types::webhooks::WebhookSubscriptionRequest;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// `sha256=<hex>`, the hex encoded HMAC-SHA256 of `{timestamp}.{body}` keyed with the subscription secret
+pub const SIGNATURE_HEADER: &str = "X-Onboarding-Signature";
+/// Unix timestamp of the attempt, part of the signed content to prevent replays
+pub const TIMESTAMP_HEADER: &str = "X-Onboarding-Timestamp";
+
+/// Event types a subscription can list
+pub const WEBHOOK_EVENT_TYPES: &[&str] = &[
+    "deposit.indexed",
+    "grant.issued",
+    "grant.failed",
+    "deposit.executed",
+];
+/// Secrets shorter than this are rejected, the signature would be easy to forge
+const MIN_SECRET_LENGTH: usize = 32;
+
+const MAX_DELIVERY_ATTEMPTS: i32 = 6;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    event: &'static str,
+    created_at: i64,
+    data: &'a AddressEvent,
+}
+
+/// Name of the webhook event matching an address event, if webhooks are sent for it
+pub fn webhook_event_type(event: &AddressEvent) -> Option<&'static str> {
+    match event {
+        AddressEvent::DepositDetected { .. } => Some("deposit.indexed"),
+        AddressEvent::FeeGrantConfirmed { .. } => Some("grant.issued"),
+        AddressEvent::FeeGrantFailed { .. } => Some("grant.failed"),
+        AddressEvent::DepositExecuted { .. } => Some("deposit.executed"),
+        AddressEvent::FeeGrantPending { .. } => None,
+    }
+}
+
+/// Subscriptions list their events separated by commas, `*` subscribes to all of them
+fn is_subscribed(subscription: &webhook_subscription::Model, event_type: &str) -> bool {
+    subscription
+        .events
+        .split(',')
+        .map(str::trim)
+        .any(|e| e == "*" || e == event_type)
+}
+
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub async fn create_subscription(
+    db: &DatabaseConnection,
+    request: WebhookSubscriptionRequest,
+) -> Result<webhook_subscription::Model, ApiError> {
+    if !request.url.starts_with("https://") && !request.url.starts_with("http://") {
+        return Err(ApiError::InvalidPayload(format!(
+            "invalid webhook url {}",
+            request.url
+        )));
+    }
+    if request.secret.len() < MIN_SECRET_LENGTH {
+        return Err(ApiError::InvalidPayload(format!(
+            "the secret should be at least {} characters",
+            MIN_SECRET_LENGTH
+        )));
+    }
+    if request.events.is_empty() {
+        return Err(ApiError::InvalidPayload("no event type".to_string()));
+    }
+    if let Some(unknown) = request
+        .events
+        .iter()
+        .find(|e| *e != "*" && !WEBHOOK_EVENT_TYPES.contains(&e.as_str()))
+    {
+        return Err(ApiError::InvalidPayload(format!(
+            "unknown event type {}",
+            unknown
+        )));
+    }
+
+    Ok(webhook_subscription::ActiveModel {
+        url: Set(request.url),
+        secret: Set(request.secret),
+        events: Set(request.events.join(",")),
+        active: Set(true.into()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?)
+}
+
+/// Stops the deliveries to a subscription, its delivery log is kept
+pub async fn deactivate_subscription(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<webhook_subscription::Model, ApiError> {
+    let subscription = webhook_subscription::Entity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::InvalidQuery(format!("unknown webhook subscription {}", id)))?;
+    let mut subscription: webhook_subscription::ActiveModel = subscription.into();
+    subscription.active = Set(false.into());
+    Ok(subscription.update(db).await?)
+}
+
+/// Forwards the address events to the webhook subscriptions until the bus closes
+pub fn spawn_webhook_dispatcher(
+    events: &EventBus,
+    db: DatabaseConnection,
+    tasks: TaskTracker,
+) -> JoinHandle<()> {
+    let mut receiver = events.subscribe();
+    let client = reqwest::Client::new();
+
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::error!("Webhook dispatcher skipped {} events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let Some(event_type) = webhook_event_type(&event) else {
+                continue;
+            };
+            if let Err(e) = dispatch(&client, &db, &tasks, event_type, &event).await {
+                tracing::error!("Could not dispatch {} webhooks: {}", event_type, e);
+            }
+        }
+    })
+}
+
+async fn dispatch(
+    client: &reqwest::Client,
+    db: &DatabaseConnection,
+    tasks: &TaskTracker,
+    event_type: &'static str,
+    event: &AddressEvent,
+) -> Result<(), ApiError> {
+    let subscriptions = webhook_subscription::Entity::find()
+        .filter(webhook_subscription::Column::Active.eq(true))
+        .all(db)
+        .await?;
+
+    let payload = serde_json::to_value(WebhookPayload {
+        event: event_type,
+        created_at: Utc::now().timestamp(),
+        data: event,
+    })?;
+
+    for subscription in subscriptions
+        .into_iter()
+        .filter(|s| is_subscribed(s, event_type))
+    {
+        tasks.spawn(deliver(
+            client.clone(),
+            db.clone(),
+            subscription,
+            event_type,
+            payload.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Posts the payload to the subscription, retrying with an exponential backoff.
+/// Every attempt is saved in the delivery log
+async fn deliver(
+    client: reqwest::Client,
+    db: DatabaseConnection,
+    subscription: webhook_subscription::Model,
+    event_type: &'static str,
+    payload: serde_json::Value,
+) {
+    // The signature covers the exact bytes that are sent
+    let body = payload.to_string();
+    let mut delay = INITIAL_RETRY_DELAY;
+
+    for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+        let timestamp = Utc::now().timestamp();
+        let response = client
+            .post(&subscription.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header(CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(
+                SIGNATURE_HEADER,
+                sign_payload(&subscription.secret, timestamp, body.as_bytes()),
+            )
+            .body(body.clone())
+            .send()
+            .await;
+
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => (Some(response.status()), None),
+            Ok(response) => (
+                Some(response.status()),
+                Some(format!("Unexpected status {}", response.status())),
+            ),
+            Err(e) => (e.status(), Some(e.to_string())),
+        };
+        let success = error.is_none();
+
+        let delivery = webhook_delivery::ActiveModel {
+            subscription_id: Set(subscription.id),
+            event_type: Set(event_type.to_string()),
+            payload: Set(payload.clone()),
+            attempt: Set(attempt),
+            status_code: Set(status_code.map(|s| s.as_u16().into())),
+            error: Set(error.clone()),
+            success: Set(success.into()),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        if let Err(e) = delivery.insert(&db).await {
+            tracing::error!("Could not save webhook delivery: {}", e);
+        }
+
+        if success {
+            return;
+        }
+        tracing::warn!(
+            "Webhook {} to {} failed (attempt {}): {:?}",
+            event_type,
+            subscription.url,
+            attempt,
+            error
+        );
+        if attempt < MAX_DELIVERY_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    tracing::error!(
+        "Giving up on webhook {} to {} after {} attempts",
+        event_type,
+        subscription.url,
+        MAX_DELIVERY_ATTEMPTS
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_the_hmac_of_the_timestamp_and_body() {
+        let signature = sign_payload("secret", 1_700_000_000, b"{\"event\":\"grant.issued\"}");
+
+        let mut mac = HmacSha256::new_from_slice(b"secret").unwrap();
+        mac.update(b"1700000000.{\"event\":\"grant.issued\"}");
+        let expected = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(signature, format!("sha256={}", expected));
+    }
+
+    #[test]
+    fn signature_depends_on_every_input() {
+        let signature = sign_payload("secret", 1, b"body");
+        assert_ne!(signature, sign_payload("other", 1, b"body"));
+        assert_ne!(signature, sign_payload("secret", 2, b"body"));
+        assert_ne!(signature, sign_payload("secret", 1, b"other"));
+    }
+}
@@ -0,0 +1,350 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use entities::fee_grant;
+use onboarding_api::admin::{audit, audit_outcome, query_indexer_cursors};
+use onboarding_api::backfill::{
+    backfill_progress, create_backfill_job, recent_backfill_jobs, run_backfill_job,
+};
+use onboarding_api::cache::fee_grant_key;
+use onboarding_api::config::Config;
+use onboarding_api::db_helpers::{pending_deposits, set_tx_executed, GRANT_STATUS_ACTIVE};
+use onboarding_api::error::ApiError;
+use onboarding_api::fee_grants::{grant, revoke, simulate_grant};
+use onboarding_api::gas::gas_price;
+use onboarding_api::kado::{reconcile, unreconciled_orders};
+use onboarding_api::shutdown::shutdown_signal;
+use onboarding_api::sweeper::sweep;
+use onboarding_api::tx_indexer::{run_indexer, run_reindexer};
+use onboarding_api::types::admin::AdminIdentity;
+use onboarding_api::types::backfill::BackfillRequest;
+use onboarding_api::types::txs::TxDto;
+use onboarding_api::{init_state, AppState};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde_json::json;
+use tracing_subscriber::EnvFilter;
+
+use crate::output::{print, OutputFormat};
+
+mod output;
+
+/// Time between two progress lines of a running backfill
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Operations on the onboarding API database and granter
+#[derive(Parser)]
+#[command(name = "onboarding-cli", version)]
+struct Cli {
+    /// Same config file as the API
+    #[arg(long, global = true, env = "ONBOARDING_CONFIG")]
+    config: Option<PathBuf>,
+    #[arg(long, short, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Indexes the new txs of an address
+    Index {
+        address: String,
+        /// Walks all the tx pages instead of starting from the saved count
+        #[arg(long)]
+        from_start: bool,
+    },
+    /// Scans a height or date range again and saves the txs that were missed.
+    /// Interrupted jobs can be resumed, by this command or on the next API start
+    Backfill {
+        /// Addresses to scan, all the receivers of the deposit denoms when none is given
+        addresses: Vec<String>,
+        #[arg(long)]
+        from_height: Option<i64>,
+        #[arg(long)]
+        to_height: Option<i64>,
+        /// RFC 3339 date, used when --from-height is not given
+        #[arg(long, conflicts_with = "from_height")]
+        from_date: Option<DateTime<Utc>>,
+        /// RFC 3339 date, exclusive, used when --to-height is not given
+        #[arg(long, conflicts_with = "to_height")]
+        to_date: Option<DateTime<Utc>>,
+        /// Resumes this job instead of creating one, failed segments are retried
+        #[arg(long, conflicts_with_all = ["addresses", "from_height", "to_height", "from_date", "to_date"])]
+        resume: Option<i32>,
+    },
+    /// Shows the progress of a backfill job, or lists the latest jobs
+    BackfillStatus { id: Option<i32> },
+    /// Grants an allowance, without the risk checks
+    Grant { address: String },
+    /// Revokes the allowance of an address
+    Revoke { address: String },
+    /// Shows the current allowance of an address and what a grant would do
+    Allowance { address: String },
+    /// Lists the deposits that were not executed yet
+    PendingDeposits {
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Marks a deposit as executed
+    MarkExecuted {
+        address: String,
+        tx_hash: String,
+        /// Marks it as not executed instead
+        #[arg(long)]
+        undo: bool,
+    },
+    /// Matches the Kado orders with the deposits again and lists the ones left
+    Reconcile,
+    /// Exports a report
+    Report {
+        #[arg(value_enum)]
+        kind: ReportKind,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportKind {
+    /// Number of txs indexed by address
+    Indexer,
+    /// Active allowances of the grant ledger
+    Grants,
+    /// Grants the sweeper would revoke
+    Sweep,
+    /// Kado orders left without a matching deposit by the last reconciliation
+    Reconciliation,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ApiError> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    // Logs go to stderr, stdout is kept for the output
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "warn".into()))
+        .init();
+
+    // The CORS and the other server sections are not needed to run the commands
+    let config = Config::resolve(cli.config.as_deref())?;
+    config.validate_for_cli()?;
+    let state = init_state(config).await?;
+    run(&state, cli.command, cli.output).await
+}
+
+async fn run(
+    state: &Arc<AppState>,
+    command: Command,
+    output: OutputFormat,
+) -> Result<(), ApiError> {
+    // CLI actions are audited like the admin API ones
+    let identity = AdminIdentity {
+        key_name: format!("cli:{}", std::env::var("USER").unwrap_or_default()),
+    };
+
+    match command {
+        Command::Index {
+            address,
+            from_start,
+        } => {
+            let (action, outcome) = if from_start {
+                (
+                    "reindex",
+                    run_reindexer(state.clone(), address.clone()).await,
+                )
+            } else {
+                ("index", run_indexer(state.clone(), address.clone()).await)
+            };
+            audit_outcome(
+                &state.db,
+                &identity,
+                action,
+                Some(address.clone()),
+                &outcome,
+                |_| None,
+            )
+            .await?;
+            outcome?;
+            print(&json!({ "indexed": address }), output)
+        }
+        Command::Backfill {
+            addresses,
+            from_height,
+            to_height,
+            from_date,
+            to_date,
+            resume,
+        } => {
+            let job_id = match resume {
+                Some(job_id) => job_id,
+                None => {
+                    let request = BackfillRequest {
+                        addresses,
+                        from_height,
+                        to_height,
+                        from_date,
+                        to_date,
+                    };
+                    create_backfill_job(state, request, &identity.key_name)
+                        .await?
+                        .id
+                }
+            };
+            let action = if resume.is_some() {
+                "resume_backfill"
+            } else {
+                "backfill"
+            };
+            audit(
+                &state.db,
+                &identity,
+                action,
+                None,
+                Some(json!({ "job_id": job_id })),
+            )
+            .await?;
+
+            // Ctrl+C stops after the current pages, the job can be resumed later
+            tokio::spawn(shutdown_signal(state.shutdown.clone()));
+            let reporter = tokio::spawn(report_progress(state.clone(), job_id));
+            let progress = run_backfill_job(state.clone(), job_id).await;
+            reporter.abort();
+            print(&progress?, output)
+        }
+        Command::BackfillStatus { id } => match id {
+            Some(id) => print(&backfill_progress(&state.db, id).await?, output),
+            None => print(&recent_backfill_jobs(&state.db).await?, output),
+        },
+        Command::Grant { address } => {
+            let outcome = grant(state, address.clone()).await;
+            state
+                .cache
+                .invalidate(&fee_grant_key(&state.sender, &address))
+                .await;
+            audit_outcome(
+                &state.db,
+                &identity,
+                "grant",
+                Some(address.clone()),
+                &outcome,
+                |response| Some(json!({ "response": response })),
+            )
+            .await?;
+            let response = outcome?;
+            print(&json!({ "address": address, "response": response }), output)
+        }
+        Command::Revoke { address } => {
+            let outcome = revoke(state, address.clone()).await;
+            state
+                .cache
+                .invalidate(&fee_grant_key(&state.sender, &address))
+                .await;
+            audit_outcome(
+                &state.db,
+                &identity,
+                "revoke",
+                Some(address.clone()),
+                &outcome,
+                |response| Some(json!({ "response": response })),
+            )
+            .await?;
+            let response = outcome?;
+            print(&json!({ "address": address, "response": response }), output)
+        }
+        Command::Allowance { address } => {
+            let simulation = simulate_grant(
+                state.channel.clone(),
+                state.sender.clone(),
+                address,
+                gas_price(state).await,
+                &state.config.fees,
+            )
+            .await?;
+            print(&simulation, output)
+        }
+        Command::PendingDeposits { address } => {
+            let deposits = pending_deposits(address, &state.db).await?;
+            print(
+                &deposits.into_iter().map(TxDto::from).collect::<Vec<_>>(),
+                output,
+            )
+        }
+        Command::MarkExecuted {
+            address,
+            tx_hash,
+            undo,
+        } => {
+            let outcome = set_tx_executed(address.clone(), tx_hash.clone(), !undo, &state.db).await;
+            let action = if undo {
+                "unmark_executed"
+            } else {
+                "mark_executed"
+            };
+            audit_outcome(
+                &state.db,
+                &identity,
+                action,
+                Some(address),
+                &outcome,
+                |found| Some(json!({ "tx_hash": tx_hash, "found": found })),
+            )
+            .await?;
+            let found = outcome?;
+            print(&json!({ "tx_hash": tx_hash, "found": found }), output)
+        }
+        Command::Reconcile => {
+            let outcome = reconcile(&state.db).await;
+            audit_outcome(
+                &state.db,
+                &identity,
+                "reconcile",
+                None,
+                &outcome,
+                |report| {
+                    Some(json!({
+                        "unmatched": report.unmatched.len(),
+                        "mismatched": report.mismatched.len(),
+                    }))
+                },
+            )
+            .await?;
+            print(&outcome?, output)
+        }
+        Command::Report { kind } => match kind {
+            ReportKind::Indexer => print(&query_indexer_cursors(&state.db).await?, output),
+            ReportKind::Grants => {
+                let grants = fee_grant::Entity::find()
+                    .filter(fee_grant::Column::Status.eq(GRANT_STATUS_ACTIVE))
+                    .order_by_asc(fee_grant::Column::GrantedAt)
+                    .all(&state.db)
+                    .await?;
+                print(&grants, output)
+            }
+            ReportKind::Sweep => print(&sweep(state, true).await?.candidates, output),
+            ReportKind::Reconciliation => print(&unreconciled_orders(&state.db).await?, output),
+        },
+    }
+}
+
+/// Prints the progress of the job on stderr until aborted
+async fn report_progress(state: Arc<AppState>, job_id: i32) {
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    loop {
+        interval.tick().await;
+        match backfill_progress(&state.db, job_id).await {
+            Ok(progress) => eprintln!(
+                "backfill {}: {:.1}% ({}/{} segments), {} txs and {} deposits saved",
+                job_id,
+                progress.percent,
+                progress.job.segments_done,
+                progress.job.segments_total,
+                progress.job.txs_saved,
+                progress.job.deposits_found
+            ),
+            Err(e) => eprintln!("backfill {}: could not read the progress: {}", job_id, e),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use clap::ValueEnum;
+use comfy_table::{presets::UTF8_FULL, ContentArrangement, Table};
+use serde::Serialize;
+use serde_json::Value;
+
+use onboarding_api::error::ApiError;
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Prints the result of a command. Lists of objects become one row per object,
+/// objects become one row per field
+pub fn print<T: Serialize>(value: &T, format: OutputFormat) -> Result<(), ApiError> {
+    let value = serde_json::to_value(value)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        OutputFormat::Table => println!("{}", to_table(&value)),
+    }
+    Ok(())
+}
+
+fn to_table(value: &Value) -> String {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic);
+
+    match value {
+        Value::Array(rows) if rows.is_empty() => return "No results".to_string(),
+        Value::Array(rows) => {
+            let Some(Value::Object(first)) = rows.first() else {
+                rows.iter().for_each(|row| {
+                    table.add_row(vec![cell(row)]);
+                });
+                return table.to_string();
+            };
+            let columns: Vec<&String> = first.keys().collect();
+            table.set_header(columns.clone());
+            for row in rows {
+                table.add_row(
+                    columns
+                        .iter()
+                        .map(|column| row.get(column.as_str()).map(cell).unwrap_or_default())
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        Value::Object(fields) => {
+            table.set_header(vec!["field", "value"]);
+            for (field, value) in fields {
+                table.add_row(vec![field.clone(), cell(value)]);
+            }
+        }
+        other => return cell(other),
+    }
+    table.to_string()
+}
+
+/// Nested values are shown as compact JSON
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
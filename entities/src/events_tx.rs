@@ -17,9 +17,21 @@ pub struct Model {
     pub kado_amount: Option<String>,
     pub has_fee_grant: i8,
     pub executed: i8,
+    pub denom: Option<String>,
+    pub sender: Option<String>,
+    pub fee_grant_requested_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::kado_order::Entity")]
+    KadoOrder,
+}
+
+impl Related<super::kado_order::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::KadoOrder.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,5 +2,15 @@
 
 pub mod prelude;
 
+pub mod admin_audit;
+pub mod backfill_job;
+pub mod backfill_segment;
 pub mod events_info;
 pub mod events_tx;
+pub mod fee_grant;
+pub mod idempotency_key;
+pub mod kado_order;
+pub mod risk_decision;
+pub mod service_flag;
+pub mod webhook_delivery;
+pub mod webhook_subscription;
@@ -1,4 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.10
 
+pub use super::admin_audit::Entity as AdminAudit;
+pub use super::backfill_job::Entity as BackfillJob;
+pub use super::backfill_segment::Entity as BackfillSegment;
 pub use super::events_info::Entity as EventsInfo;
 pub use super::events_tx::Entity as EventsTx;
+pub use super::fee_grant::Entity as FeeGrant;
+pub use super::idempotency_key::Entity as IdempotencyKey;
+pub use super::kado_order::Entity as KadoOrder;
+pub use super::risk_decision::Entity as RiskDecision;
+pub use super::service_flag::Entity as ServiceFlag;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
+pub use super::webhook_subscription::Entity as WebhookSubscription;
@@ -0,0 +1,12 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum AdminAudit {
+    Table,
+    Id,
+    KeyName,
+    Action,
+    Target,
+    Details,
+    CreatedAt,
+}
@@ -0,0 +1,20 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum BackfillJob {
+    Table,
+    Id,
+    TargetKind,
+    Targets,
+    FromHeight,
+    ToHeight,
+    Status,
+    SegmentsTotal,
+    SegmentsDone,
+    TxsSaved,
+    DepositsFound,
+    CreatedBy,
+    CreatedAt,
+    UpdatedAt,
+    FinishedAt,
+}
@@ -0,0 +1,17 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum BackfillSegment {
+    Table,
+    Id,
+    JobId,
+    Target,
+    FromHeight,
+    ToHeight,
+    NextPage,
+    Status,
+    TxsSaved,
+    DepositsFound,
+    Error,
+    UpdatedAt,
+}
@@ -11,4 +11,7 @@ pub enum EventsTx {
     KadoAmount,
     HasFeeGrant,
     Executed,
+    Denom,
+    Sender,
+    FeeGrantRequestedAt,
 }
@@ -0,0 +1,14 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum FeeGrant {
+    Table,
+    Id,
+    Grantee,
+    SpendLimit,
+    Status,
+    GrantTxHash,
+    RevokeTxHash,
+    GrantedAt,
+    RevokedAt,
+}
@@ -0,0 +1,13 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum IdempotencyKey {
+    Table,
+    Id,
+    Key,
+    Status,
+    ResponseStatus,
+    ResponseBody,
+    CreatedAt,
+    ExpiresAt,
+}
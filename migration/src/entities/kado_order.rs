@@ -0,0 +1,16 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum KadoOrder {
+    Table,
+    Id,
+    OrderId,
+    Receiver,
+    Amount,
+    Status,
+    OrderedAt,
+    Payload,
+    MatchStatus,
+    MatchedTxId,
+    CreatedAt,
+}
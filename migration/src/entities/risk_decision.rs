@@ -0,0 +1,13 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum RiskDecision {
+    Table,
+    Id,
+    Address,
+    TxHash,
+    CheckName,
+    Allowed,
+    Reason,
+    CreatedAt,
+}
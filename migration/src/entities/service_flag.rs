@@ -0,0 +1,10 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum ServiceFlag {
+    Table,
+    Name,
+    Enabled,
+    UpdatedBy,
+    UpdatedAt,
+}
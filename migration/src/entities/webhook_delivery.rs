@@ -0,0 +1,15 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum WebhookDelivery {
+    Table,
+    Id,
+    SubscriptionId,
+    EventType,
+    Payload,
+    Attempt,
+    StatusCode,
+    Error,
+    Success,
+    CreatedAt,
+}
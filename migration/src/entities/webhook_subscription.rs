@@ -0,0 +1,12 @@
+use sea_orm_migration::sea_orm::DeriveIden;
+
+#[derive(DeriveIden)]
+pub enum WebhookSubscription {
+    Table,
+    Id,
+    Url,
+    Secret,
+    Events,
+    Active,
+    CreatedAt,
+}
@@ -2,11 +2,35 @@ pub use sea_orm_migration::prelude::*;
 
 pub mod entities;
 mod m20220101_000001_create_table;
+mod m20261019_000002_add_deposit_denom;
+mod m20261019_000003_create_webhooks;
+mod m20261019_000004_create_kado_order;
+mod m20261019_000005_create_risk_decision;
+mod m20261019_000006_create_admin_audit;
+mod m20261019_000007_create_fee_grant;
+mod m20261019_000008_create_idempotency_key;
+mod m20261019_000009_add_fee_grant_requested_at;
+mod m20261019_000010_create_backfill_job;
+mod m20261019_000011_create_service_flag;
+mod m20261019_000012_dedup_events_tx;
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261019_000002_add_deposit_denom::Migration),
+            Box::new(m20261019_000003_create_webhooks::Migration),
+            Box::new(m20261019_000004_create_kado_order::Migration),
+            Box::new(m20261019_000005_create_risk_decision::Migration),
+            Box::new(m20261019_000006_create_admin_audit::Migration),
+            Box::new(m20261019_000007_create_fee_grant::Migration),
+            Box::new(m20261019_000008_create_idempotency_key::Migration),
+            Box::new(m20261019_000009_add_fee_grant_requested_at::Migration),
+            Box::new(m20261019_000010_create_backfill_job::Migration),
+            Box::new(m20261019_000011_create_service_flag::Migration),
+            Box::new(m20261019_000012_dedup_events_tx::Migration),
+        ]
     }
 }
@@ -0,0 +1,63 @@
+use crate::entities::events_tx::EventsTx;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Denom used for every deposit indexed before the column existed
+const AXL_USDC_DENOM: &str = "ibc/B3504E092456BA618CC28AC671A71FB08C6CA0FD0BE7C8A5B5A3E2DD933CC9E4";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .add_column(ColumnDef::new(EventsTx::Denom).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Only axlUSDC deposits were tracked until now, they all get that denom
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(EventsTx::Table)
+                    .value(EventsTx::Denom, AXL_USDC_DENOM)
+                    .and_where(Expr::col(EventsTx::KadoAmount).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-events_tx-address-timestamp")
+                    .table(EventsTx::Table)
+                    .col(EventsTx::Address)
+                    .col(EventsTx::Timestamp)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-events_tx-address-timestamp")
+                    .table(EventsTx::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .drop_column(EventsTx::Denom)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,72 @@
+use crate::entities::{events_tx::EventsTx, kado_order::KadoOrder};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(KadoOrder::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(KadoOrder::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(KadoOrder::OrderId)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(KadoOrder::Receiver).string().not_null())
+                    .col(ColumnDef::new(KadoOrder::Amount).string().not_null())
+                    .col(ColumnDef::new(KadoOrder::Status).string().not_null())
+                    .col(ColumnDef::new(KadoOrder::OrderedAt).timestamp().not_null())
+                    .col(ColumnDef::new(KadoOrder::Payload).json().not_null())
+                    .col(
+                        ColumnDef::new(KadoOrder::MatchStatus)
+                            .string()
+                            .not_null()
+                            .default("unmatched"),
+                    )
+                    .col(ColumnDef::new(KadoOrder::MatchedTxId).integer())
+                    .col(
+                        ColumnDef::new(KadoOrder::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-kado_order-matched_tx_id")
+                            .from(KadoOrder::Table, KadoOrder::MatchedTxId)
+                            .to(EventsTx::Table, EventsTx::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-kado_order-receiver")
+                    .table(KadoOrder::Table)
+                    .col(KadoOrder::Receiver)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(KadoOrder::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,86 @@
+use crate::entities::{events_tx::EventsTx, risk_decision::RiskDecision};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The sender of the deposit is used to spot addresses funded by the same wallet
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .add_column(ColumnDef::new(EventsTx::Sender).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-events_tx-sender")
+                    .table(EventsTx::Table)
+                    .col(EventsTx::Sender)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(RiskDecision::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RiskDecision::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RiskDecision::Address).string().not_null())
+                    .col(ColumnDef::new(RiskDecision::TxHash).string().not_null())
+                    .col(ColumnDef::new(RiskDecision::CheckName).string().not_null())
+                    .col(ColumnDef::new(RiskDecision::Allowed).boolean().not_null())
+                    .col(ColumnDef::new(RiskDecision::Reason).text().not_null())
+                    .col(
+                        ColumnDef::new(RiskDecision::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-risk_decision-address")
+                    .table(RiskDecision::Table)
+                    .col(RiskDecision::Address)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RiskDecision::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-events_tx-sender")
+                    .table(EventsTx::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .drop_column(EventsTx::Sender)
+                    .to_owned(),
+            )
+            .await
+    }
+}
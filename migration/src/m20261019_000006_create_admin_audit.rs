@@ -0,0 +1,42 @@
+use crate::entities::admin_audit::AdminAudit;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AdminAudit::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AdminAudit::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AdminAudit::KeyName).string().not_null())
+                    .col(ColumnDef::new(AdminAudit::Action).string().not_null())
+                    .col(ColumnDef::new(AdminAudit::Target).string())
+                    .col(ColumnDef::new(AdminAudit::Details).json())
+                    .col(
+                        ColumnDef::new(AdminAudit::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AdminAudit::Table).to_owned())
+            .await
+    }
+}
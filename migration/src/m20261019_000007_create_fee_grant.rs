@@ -0,0 +1,54 @@
+use crate::entities::fee_grant::FeeGrant;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FeeGrant::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FeeGrant::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FeeGrant::Grantee).string().not_null())
+                    .col(ColumnDef::new(FeeGrant::SpendLimit).string().not_null())
+                    .col(ColumnDef::new(FeeGrant::Status).string().not_null())
+                    .col(ColumnDef::new(FeeGrant::GrantTxHash).string())
+                    .col(ColumnDef::new(FeeGrant::RevokeTxHash).string())
+                    .col(
+                        ColumnDef::new(FeeGrant::GrantedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(FeeGrant::RevokedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-fee_grant-grantee-status")
+                    .table(FeeGrant::Table)
+                    .col(FeeGrant::Grantee)
+                    .col(FeeGrant::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FeeGrant::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,30 @@
+use crate::entities::events_tx::EventsTx;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .add_column(ColumnDef::new(EventsTx::FeeGrantRequestedAt).timestamp())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventsTx::Table)
+                    .drop_column(EventsTx::FeeGrantRequestedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,151 @@
+use crate::entities::{backfill_job::BackfillJob, backfill_segment::BackfillSegment};
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(BackfillJob::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BackfillJob::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(BackfillJob::TargetKind).string().not_null())
+                    .col(ColumnDef::new(BackfillJob::Targets).json().not_null())
+                    .col(
+                        ColumnDef::new(BackfillJob::FromHeight)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillJob::ToHeight)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BackfillJob::Status).string().not_null())
+                    .col(
+                        ColumnDef::new(BackfillJob::SegmentsTotal)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillJob::SegmentsDone)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillJob::TxsSaved)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillJob::DepositsFound)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(BackfillJob::CreatedBy).string().not_null())
+                    .col(
+                        ColumnDef::new(BackfillJob::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillJob::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(BackfillJob::FinishedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(BackfillSegment::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BackfillSegment::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(BackfillSegment::JobId).integer().not_null())
+                    .col(ColumnDef::new(BackfillSegment::Target).string().not_null())
+                    .col(
+                        ColumnDef::new(BackfillSegment::FromHeight)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillSegment::ToHeight)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BackfillSegment::NextPage).big_integer())
+                    .col(ColumnDef::new(BackfillSegment::Status).string().not_null())
+                    .col(
+                        ColumnDef::new(BackfillSegment::TxsSaved)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(BackfillSegment::DepositsFound)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(BackfillSegment::Error).text())
+                    .col(
+                        ColumnDef::new(BackfillSegment::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-backfill_segment-job_id")
+                            .from(BackfillSegment::Table, BackfillSegment::JobId)
+                            .to(BackfillJob::Table, BackfillJob::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-backfill_segment-job_id-status")
+                    .table(BackfillSegment::Table)
+                    .col(BackfillSegment::JobId)
+                    .col(BackfillSegment::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BackfillSegment::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(BackfillJob::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,39 @@
+use crate::entities::service_flag::ServiceFlag;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ServiceFlag::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ServiceFlag::Name)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ServiceFlag::Enabled).boolean().not_null())
+                    .col(ColumnDef::new(ServiceFlag::UpdatedBy).string().not_null())
+                    .col(
+                        ColumnDef::new(ServiceFlag::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ServiceFlag::Table).to_owned())
+            .await
+    }
+}
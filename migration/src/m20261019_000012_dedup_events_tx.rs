@@ -0,0 +1,72 @@
+use crate::entities::events_tx::EventsTx;
+use sea_orm_migration::prelude::*;
+
+/// Makes the txs unique per address, deleting the copies saved by concurrent indexing.
+/// MySQL only, and it can't be reverted: `down` drops the index, the copies are gone
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Concurrent indexing could save a tx twice, only the first copy is kept.
+        // The flags and the deposit fields could be set on any copy, they are merged into it first
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "UPDATE events_tx original \
+            JOIN (SELECT MIN(id) AS id, MAX(has_fee_grant) AS has_fee_grant, \
+                MAX(executed) AS executed, MAX(kado_amount) AS kado_amount, MAX(denom) AS denom, \
+                MAX(sender) AS sender, MAX(fee_grant_requested_at) AS fee_grant_requested_at \
+                FROM events_tx GROUP BY address, tx_hash HAVING COUNT(*) > 1) merged \
+            ON original.id = merged.id \
+            SET original.has_fee_grant = GREATEST(original.has_fee_grant, merged.has_fee_grant), \
+            original.executed = GREATEST(original.executed, merged.executed), \
+            original.kado_amount = COALESCE(original.kado_amount, merged.kado_amount), \
+            original.denom = COALESCE(original.denom, merged.denom), \
+            original.sender = COALESCE(original.sender, merged.sender), \
+            original.fee_grant_requested_at = IF(merged.has_fee_grant, NULL, \
+                COALESCE(original.fee_grant_requested_at, merged.fee_grant_requested_at))",
+        )
+        .await?;
+        // The Kado orders matched with a copy would lose their match when it's deleted
+        db.execute_unprepared(
+            "UPDATE kado_order \
+            JOIN events_tx duplicate ON kado_order.matched_tx_id = duplicate.id \
+            JOIN (SELECT address, tx_hash, MIN(id) AS id FROM events_tx \
+                GROUP BY address, tx_hash HAVING COUNT(*) > 1) original \
+            ON duplicate.address = original.address AND duplicate.tx_hash = original.tx_hash \
+            SET kado_order.matched_tx_id = original.id",
+        )
+        .await?;
+        db.execute_unprepared(
+            "DELETE duplicate FROM events_tx duplicate \
+            JOIN events_tx original ON duplicate.address = original.address \
+            AND duplicate.tx_hash = original.tx_hash AND duplicate.id > original.id",
+        )
+        .await?;
+
+        // Txs are upserted on this key, so that the indexer and the backfills can overlap
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-events_tx-address-tx_hash")
+                    .table(EventsTx::Table)
+                    .col(EventsTx::Address)
+                    .col(EventsTx::TxHash)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-events_tx-address-tx_hash")
+                    .table(EventsTx::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}